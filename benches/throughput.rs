@@ -0,0 +1,109 @@
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use htmd::{MarkDown, HTML};
+
+const ITERATIONS: u32 = 100;
+const REPEAT: usize = 500;
+
+fn main() {
+    let inputs = [
+        ("prose", prose()),
+        ("lists", lists()),
+        ("code", code()),
+        ("emphasis", emphasis()),
+        ("brackets", brackets()),
+    ];
+
+    for (name, input) in inputs.iter() {
+        let parse = time(|| {
+            let _ = black_box(MarkDown::from(black_box(input.as_str())));
+        });
+
+        let html = HTML::from(MarkDown::from(input.as_str()));
+        let render = time(|| {
+            black_box(black_box(&html).to_string());
+        });
+
+        // The same buffer is reused by every render
+        let mut buffer = String::new();
+        let render_to = time(|| {
+            buffer.clear();
+            black_box(&html).render_to(&mut buffer).unwrap();
+            black_box(&buffer);
+        });
+        assert_eq!(buffer, html.to_string());
+
+        println!(
+            "{name:<10} {:>8} bytes    parse {:>10.2?} {:>8.1} MB/s    render {:>10.2?} {:>8.1} MB/s    render_to {:>10.2?} {:>8.1} MB/s",
+            input.len(),
+            parse,
+            throughput(input.len(), parse),
+            render,
+            throughput(input.len(), render),
+            render_to,
+            throughput(input.len(), render_to),
+        );
+    }
+}
+
+// Mean duration of one call
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn throughput(bytes: usize, duration: Duration) -> f64 {
+    bytes as f64 / duration.as_secs_f64() / 1_000_000.
+}
+
+fn prose() -> String {
+    "# A title\n\
+     Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt\n\
+     ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation.\n\
+     \n\
+     Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat.\n\
+     \n"
+    .repeat(REPEAT)
+}
+
+fn lists() -> String {
+    "- first item\n\
+     - second item\n    \
+         - nested item\n    \
+         - another nested item\n        \
+             1. deep ordered item\n\
+     - back to the top\n\
+     \n"
+    .repeat(REPEAT)
+}
+
+fn code() -> String {
+    "Some code:\n\
+     ```rust\n\
+     fn main() {\n    \
+         let answer = 42;\n    \
+         println!(\"{answer}\");\n\
+     }\n\
+     ```\n\
+     \n"
+    .repeat(REPEAT)
+}
+
+fn emphasis() -> String {
+    "Some *emphasis*, some **strong**, some ***both***, some `code` and ~~strike~~ with \
+     _underscores_ and __more__ of [a link](https://example.com) and ![an image](img.png).\n"
+        .repeat(REPEAT)
+}
+
+// Every bracket on the line opens a link attempt which fails, the unclosed ones only meeting a ']'
+// at the end of the line
+fn brackets() -> String {
+    "[not a link] ![nor an image] [unclosed ".repeat(REPEAT) + "]\n"
+}
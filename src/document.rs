@@ -1,63 +1,447 @@
+use alloc::{
+    borrow::{Cow, ToOwned},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, iter::Peekable, ops::Range};
+
 use bitflags::bitflags;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Document<'a> {
     pub nodes: Vec<Node<'a>>,
+    /// Lines of the source each node comes from, counted from 1 with the end excluded, in the
+    /// order `Renderer::node` sees the nodes. Empty when the nodes weren't parsed as they are.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lines: Vec<Range<usize>>,
+}
+
+/// A document which doesn't borrow from the markdown source anymore.
+pub type DocumentOwned = Document<'static>;
+
+impl<'a> Document<'a> {
+    #[must_use]
+    pub fn into_owned(self) -> DocumentOwned {
+        Document {
+            nodes: self.nodes.into_iter().map(Node::into_owned).collect(),
+            lines: self.lines,
+        }
+    }
+
+    #[must_use]
+    pub fn to_owned(&self) -> DocumentOwned {
+        self.clone().into_owned()
+    }
+
+    /// Removes the nodes, containers' children included, for which `f` returns `false`. The
+    /// `lines` of the nodes are lost.
+    pub fn retain<F: FnMut(&Node) -> bool>(&mut self, mut f: F) {
+        self.nodes = retain_nodes(core::mem::take(&mut self.nodes), &mut f);
+        self.lines.clear();
+    }
+
+    pub fn stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats::default();
+        stats.count_nodes(&self.nodes);
+        stats
+    }
+}
+
+fn retain_nodes<'a>(nodes: Vec<Node<'a>>, f: &mut impl FnMut(&Node) -> bool) -> Vec<Node<'a>> {
+    let mut retained = Vec::with_capacity(nodes.len());
+    let mut has_removed = false;
+
+    for node in nodes {
+        if !f(&node) {
+            has_removed = true;
+            continue;
+        }
+
+        // Paragraphs which were apart must not be merged together by `compacte_nodes`
+        if has_removed
+            && matches!(node, Node::Paragraphe(_))
+            && matches!(retained.last(), Some(Node::Paragraphe(_)))
+        {
+            retained.push(Node::LineBreak);
+        }
+        has_removed = false;
+
+        match node {
+            Node::Container {
+                name,
+                attrs,
+                children,
+            } => retained.push(Node::Container {
+                name,
+                attrs,
+                children: retain_nodes(children, f),
+            }),
+            node => retained.push(node),
+        }
+    }
+
+    compacte_nodes(retained)
+}
+
+#[must_use]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DocumentStats {
+    pub headers: [usize; 6], // count per level, `headers[0]` being `<h1>`
+    pub paragraphs: usize,
+    pub list_items: usize,
+    pub code_blocks: usize,
+    pub links: usize,
+    pub images: usize,
+}
+
+impl DocumentStats {
+    fn count_nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Header(level, text) => {
+                    self.headers[level.saturating_sub(1).min(5)] += 1;
+                    self.count_text(text);
+                }
+                Node::Paragraphe(text) => {
+                    self.paragraphs += 1;
+                    self.count_text(text);
+                }
+                Node::List(_, paragraphs, _) => {
+                    self.list_items += 1;
+                    for text in paragraphs {
+                        self.count_text(text);
+                    }
+                }
+                Node::Quote(_, text) | Node::Footnote(_, text) => self.count_text(text),
+                Node::Table { header, rows, .. } => {
+                    for cell in header.iter().chain(rows.iter().flatten()) {
+                        self.count_text(cell);
+                    }
+                }
+                Node::CodeBlock(_) => self.code_blocks += 1,
+                Node::Container { children, .. } => self.count_nodes(children),
+                Node::LineBreak | Node::Rule => (),
+            }
+        }
+    }
+
+    fn count_text(&mut self, text: &Text) {
+        for fragment in text.content.iter() {
+            match fragment {
+                TextFragment::Link(_, _, _) => self.links += 1,
+                TextFragment::Image(_, _, _, _) => self.images += 1,
+                TextFragment::Group(_, text) => self.count_text(text),
+                TextFragment::Stylised(_, _)
+                | TextFragment::FootnoteRef(_, _)
+                | TextFragment::HardBreak => (),
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
+/// A block of the document. New kinds of blocks may be added, so a match on them outside this
+/// crate keeps an arm for the others.
+///
+/// ```
+/// use htmd::{MarkDown, Node};
+///
+/// fn kind(node: &Node) -> &'static str {
+///     match node {
+///         Node::Header(..) => "header",
+///         Node::Paragraphe(_) => "paragraph",
+///         _ => "other",
+///     }
+/// }
+///
+/// let md = MarkDown::from("# a\nb\n\n---");
+/// let kinds: Vec<_> = md.0.nodes.iter().map(kind).collect();
+/// assert_eq!(kinds, ["header", "paragraph", "other", "other"]);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Node<'a> {
     Header(usize, Text<'a>),
     Paragraphe(Text<'a>),
-    List(ListKind, Text<'a>),
+    List(ListKind, Vec<Text<'a>>, bool), // kind, the item's paragraphs, whether its list is loose
+    Quote(usize, Text<'a>),              // deepth, from 1 for `>`
+    Table {
+        header: Vec<Text<'a>>,
+        alignments: Vec<Option<Alignment>>,
+        rows: Vec<Vec<Text<'a>>>, // as many cells as the header in each row
+    },
     CodeBlock(CodeBlock<'a>),
+    Container {
+        name: Cow<'a, str>,
+        attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+        children: Vec<Node<'a>>,
+    },
     LineBreak,
     Rule,
+    Footnote(usize, Text<'a>), // number, text
 }
 
-#[derive(Debug)]
+impl<'a> Node<'a> {
+    pub fn into_owned(self) -> Node<'static> {
+        use Node::*;
+        match self {
+            Header(level, text) => Header(level, text.into_owned()),
+            Paragraphe(text) => Paragraphe(text.into_owned()),
+            List(list_kind, paragraphs, loose) => List(
+                list_kind,
+                paragraphs.into_iter().map(Text::into_owned).collect(),
+                loose,
+            ),
+            Quote(deepth, text) => Quote(deepth, text.into_owned()),
+            Table {
+                header,
+                alignments,
+                rows,
+            } => Table {
+                header: header.into_iter().map(Text::into_owned).collect(),
+                alignments,
+                rows: rows
+                    .into_iter()
+                    .map(|row| row.into_iter().map(Text::into_owned).collect())
+                    .collect(),
+            },
+            CodeBlock(codeblock) => CodeBlock(codeblock.into_owned()),
+            Container {
+                name,
+                attrs,
+                children,
+            } => Container {
+                name: into_owned(name),
+                attrs: attrs
+                    .into_iter()
+                    .map(|(attr, value)| (into_owned(attr), into_owned(value)))
+                    .collect(),
+                children: children.into_iter().map(Node::into_owned).collect(),
+            },
+            LineBreak => LineBreak,
+            Rule => Rule,
+            Footnote(number, text) => Footnote(number, text.into_owned()),
+        }
+    }
+}
+
+#[inline]
+fn into_owned(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "SerdeCodeBlock", from = "SerdeCodeBlock")
+)]
 pub struct CodeBlock<'a> {
-    s: &'a str,
-    pub language: &'a str,
+    s: Cow<'a, str>,
+    pub language: Cow<'a, str>,
+    pub meta: Cow<'a, str>, // rest of the info string after the language, as `title=foo`
+    pub line_start: usize,
     pub code: Span,
 }
 
 impl<'a> CodeBlock<'a> {
     pub fn new(s: &'a str, language: &'a str, code: Span) -> Self {
-        Self { s, language, code }
+        Self {
+            s: s.into(),
+            language: language.into(),
+            meta: Cow::Borrowed(""),
+            line_start: 1,
+            code,
+        }
     }
 
-    pub fn fetch(&self) -> Option<&'a str> {
-        self.code.fetch(self.s)
+    pub fn from_code(code: impl Into<Cow<'a, str>>, language: &'a str) -> Self {
+        let code = code.into();
+        Self {
+            code: Span::new(0, code.len()),
+            s: code,
+            language: language.into(),
+            meta: Cow::Borrowed(""),
+            line_start: 1,
+        }
     }
+
+    // Only the code itself is copied, not the whole source the span points into
+    pub fn into_owned(self) -> CodeBlock<'static> {
+        let code = self.fetch().unwrap_or_default().to_owned();
+        CodeBlock {
+            code: Span::new(0, code.len()),
+            s: Cow::Owned(code),
+            language: into_owned(self.language),
+            meta: into_owned(self.meta),
+            line_start: self.line_start,
+        }
+    }
+
+    #[must_use]
+    pub fn with_meta(mut self, meta: impl Into<Cow<'a, str>>) -> Self {
+        self.meta = meta.into();
+        self
+    }
+
+    #[must_use]
+    pub fn with_line_start(mut self, line_start: usize) -> Self {
+        self.line_start = line_start;
+        self
+    }
+
+    pub fn fetch(&self) -> Option<&str> {
+        self.code.fetch(&self.s)
+    }
+
+    // `\r\n` and `\r` become `\n` like between the other lines of the document
+    pub(crate) fn normalize_line_endings(self) -> Self {
+        match self.fetch() {
+            Some(code) if code.contains('\r') => {
+                let code = code.replace("\r\n", "\n").replace('\r', "\n");
+                Self {
+                    code: Span::new(0, code.len()),
+                    s: Cow::Owned(code),
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    // The last line of a code block the input ended in, without a newline to close it
+    pub(crate) fn end_last_line(self) -> Self {
+        match self.fetch() {
+            Some(code) if !code.is_empty() && !code.ends_with('\n') => {
+                let code = format!("{code}\n");
+                Self {
+                    code: Span::new(0, code.len()),
+                    s: Cow::Owned(code),
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    pub(crate) fn into_code(self) -> Cow<'a, str> {
+        match self.s {
+            Cow::Borrowed(s) => Cow::Borrowed(self.code.fetch(s).unwrap_or_default()),
+            Cow::Owned(s) => Cow::Owned(self.code.fetch(&s).unwrap_or_default().to_owned()),
+        }
+    }
+}
+
+// The code alone rather than the whole source its span points into
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeCodeBlock<'a> {
+    language: Cow<'a, str>,
+    meta: Cow<'a, str>,
+    line_start: usize,
+    code: Cow<'a, str>,
 }
 
-#[derive(Debug)]
+#[cfg(feature = "serde")]
+impl<'a> From<CodeBlock<'a>> for SerdeCodeBlock<'a> {
+    fn from(codeblock: CodeBlock<'a>) -> Self {
+        Self {
+            language: codeblock.language.clone(),
+            meta: codeblock.meta.clone(),
+            line_start: codeblock.line_start,
+            code: codeblock.into_code(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a> From<SerdeCodeBlock<'a>> for CodeBlock<'a> {
+    fn from(codeblock: SerdeCodeBlock<'a>) -> Self {
+        Self {
+            code: Span::new(0, codeblock.code.len()),
+            s: codeblock.code,
+            language: codeblock.language,
+            meta: codeblock.meta,
+            line_start: codeblock.line_start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ListKind {
-    Oredred(usize),
+    Oredred(usize, char), // deepth, `.` or `)` following the number
     Unordere(usize),
+    Task(usize, bool), // deepth, checked
 }
 
 impl ListKind {
     #[inline]
     pub const fn deepth(&self) -> usize {
         match self {
-            Self::Oredred(d) | Self::Unordere(d) => *d,
+            Self::Oredred(d, _) | Self::Unordere(d) | Self::Task(d, _) => *d,
+        }
+    }
+
+    // An item goes on with the list at its deepth, unless one is numbered and not the other, or
+    // their numbers end differently
+    pub(crate) fn continues(&self, list: &ListKind) -> bool {
+        match (self, list) {
+            (Self::Oredred(_, delimiter), Self::Oredred(_, list_delimiter))
+                if delimiter != list_delimiter =>
+            {
+                false
+            }
+            (Self::Oredred(_, _), Self::Unordere(_) | Self::Task(_, _))
+            | (Self::Unordere(_) | Self::Task(_, _), Self::Oredred(_, _)) => false,
+            _ => self.deepth() == list.deepth(),
+        }
+    }
+
+    // A task stands for a plain item once it has no box to check
+    pub(crate) const fn with_deepth(&self, deepth: usize) -> Self {
+        match self {
+            Self::Oredred(_, delimiter) => Self::Oredred(deepth, *delimiter),
+            Self::Unordere(_) | Self::Task(_, _) => Self::Unordere(deepth),
         }
     }
 }
 
 pub fn compacte_nodes(nodes: Vec<Node>) -> Vec<Node> {
+    compacte_lined_nodes(nodes.into_iter().map(|node| (node, 0..0)).collect())
+        .into_iter()
+        .map(|(node, _)| node)
+        .collect()
+}
+
+// Nodes along the lines they come from, which merge as the nodes do
+pub(crate) type LinedNode<'a> = (Node<'a>, Range<usize>);
+
+pub(crate) fn compacte_lined_nodes(nodes: Vec<LinedNode>) -> Vec<LinedNode> {
     use Node::*;
 
     let mut new_nodes = Vec::with_capacity(nodes.len());
 
-    let mut in_paragraphe = None;
+    let mut in_paragraphe: Option<(Text, Range<usize>)> = None;
     let mut has_br = false;
 
-    for node in nodes {
-        if !matches!(node, Paragraphe(_)) && let Some(text) = in_paragraphe.take() {
-            new_nodes.push(Paragraphe(text))
+    for (node, lines) in nodes {
+        if !matches!(node, Paragraphe(_)) {
+            if let Some((mut text, p_lines)) = in_paragraphe.take() {
+                text.trim_hard_break();
+                new_nodes.push((Paragraphe(text), p_lines))
+            }
         }
 
         if !matches!(node, LineBreak) {
@@ -66,80 +450,367 @@ pub fn compacte_nodes(nodes: Vec<Node>) -> Vec<Node> {
 
         match node {
             Paragraphe(text) => {
-                if let Some(p_text) = in_paragraphe.as_mut() {
-                    p_text.appendnl(text)
+                if let Some((p_text, p_lines)) = in_paragraphe.as_mut() {
+                    p_text.appendnl(text);
+                    p_lines.end = lines.end;
                 } else {
-                    in_paragraphe = Some(text)
+                    in_paragraphe = Some((text, lines))
                 }
             }
             LineBreak => {
                 if !has_br {
-                    new_nodes.push(node);
+                    new_nodes.push((node, lines));
                     has_br = true;
                 }
             }
-            Header(_, _) => {
+            // The blank lines after a header or a table only set it apart
+            Header(_, _) | Table { .. } => {
                 has_br = true;
-                new_nodes.push(node)
+                new_nodes.push((node, lines))
             }
-            _ => new_nodes.push(node),
+            Quote(deepth, text) => match new_nodes.last_mut() {
+                Some((Quote(pre_deepth, pre_text), pre_lines)) if *pre_deepth == deepth => {
+                    pre_text.appendnl(text);
+                    pre_lines.end = lines.end;
+                }
+                _ => new_nodes.push((Quote(deepth, text), lines)),
+            },
+            _ => new_nodes.push((node, lines)),
+        }
+    }
+
+    if let Some((mut text, p_lines)) = in_paragraphe.take() {
+        text.trim_hard_break();
+        new_nodes.push((Paragraphe(text), p_lines))
+    }
+
+    // As paragraphs, quotes and list items don't end with a hard break
+    for (node, _) in new_nodes.iter_mut() {
+        match node {
+            Quote(_, text) => text.trim_hard_break(),
+            List(_, paragraphs, _) => paragraphs.iter_mut().for_each(Text::trim_hard_break),
+            _ => (),
         }
     }
 
-    if let Some(text) = in_paragraphe.take() {
-        new_nodes.push(Paragraphe(text))
+    loosen_lists(new_nodes)
+}
+
+// A list is loose when blank lines part its items, or the blocks of one of them, the blank lines
+// being dropped for it
+fn loosen_lists(nodes: Vec<LinedNode>) -> Vec<LinedNode> {
+    use Node::*;
+
+    let mut new_nodes = Vec::with_capacity(nodes.len());
+    // The list of each item, numbered in the order they start, and whether that list is loose
+    let mut item_lists = Vec::new();
+    let mut loose = Vec::new();
+    let mut open_lists: Vec<(ListKind, usize)> = Vec::new(); // kind, list
+    let mut after_blank = false;
+
+    let mut nodes = nodes.into_iter().peekable();
+    while let Some((node, lines)) = nodes.next() {
+        let List(kind, paragraphs, _) = &node else {
+            if matches!(node, LineBreak)
+                && !open_lists.is_empty()
+                && matches!(nodes.peek(), Some((List(_, _, _), _)))
+            {
+                after_blank = true;
+            } else {
+                open_lists.clear();
+                new_nodes.push((node, lines));
+            }
+            continue;
+        };
+
+        let deepth = kind.deepth();
+        while open_lists.last().is_some_and(|(list_kind, _)| {
+            list_kind.deepth() >= deepth && !kind.continues(list_kind)
+        }) {
+            open_lists.pop();
+        }
+        let list = match open_lists.last() {
+            Some((list_kind, list)) if list_kind.deepth() == deepth => {
+                loose[*list] |= after_blank;
+                *list
+            }
+            parent => {
+                // A blank line before a nested list parts the blocks of the item holding it
+                if let Some((_, parent)) = parent {
+                    loose[*parent] |= after_blank;
+                }
+                loose.push(false);
+                open_lists.push((*kind, loose.len() - 1));
+                loose.len() - 1
+            }
+        };
+        loose[list] |= paragraphs.len() > 1;
+        after_blank = false;
+
+        item_lists.push(list);
+        new_nodes.push((node, lines));
     }
 
+    let mut item_lists = item_lists.into_iter();
+    for (node, _) in new_nodes.iter_mut() {
+        if let List(_, _, is_loose) = node {
+            *is_loose = item_lists.next().is_some_and(|list| loose[list]);
+        }
+    }
     new_nodes
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Text<'a> {
     pub content: Vec<TextFragment<'a>>,
 }
 impl<'a> Text<'a> {
-    fn appendnl(&mut self, mut text: Text<'a>) {
+    pub fn into_owned(self) -> Text<'static> {
+        Text {
+            content: self
+                .content
+                .into_iter()
+                .map(TextFragment::into_owned)
+                .collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn to_plain_text(&self) -> String {
+        let mut plain_text = String::new();
+        for frag in self.content.iter() {
+            match frag {
+                TextFragment::Stylised(style, text) if !style.contains(Style::Modifier) => {
+                    plain_text.push_str(text)
+                }
+                TextFragment::Group(_, text) => plain_text.push_str(&text.to_plain_text()),
+                TextFragment::Link(alt, _, _) => plain_text.push_str(&alt.to_plain_text()),
+                TextFragment::Image(alt, _, _, _) => plain_text.push_str(alt),
+                _ => (),
+            }
+        }
+        plain_text
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.content.iter().map(TextFragment::len).sum()
+    }
+
+    /// The fragments as a tree of groups of one style each, e.g. `**a *b* c**` gives one strong
+    /// group holding an emphasis group, the delimiters left out.
+    #[must_use]
+    pub fn inlines(&self) -> Vec<Inline<'_>> {
+        inlines(&self.content)
+    }
+
+    /// Anchor id built from the visible text, e.g. `**Bold** Title` gives `bold-title`.
+    #[must_use]
+    pub fn slug(&self) -> String {
+        self.to_plain_text()
+            .trim()
+            .chars()
+            .filter_map(|c| match c {
+                c if c.is_whitespace() => Some('-'),
+                '-' | '_' => Some(c),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    fn appendnl(&mut self, text: Text<'a>) {
+        self.join(text, "\n")
+    }
+
+    // A hard break ending a paragraph has no line left to break, the backslash written for it
+    // staying literal
+    pub(crate) fn trim_hard_break(&mut self) {
+        if self
+            .content
+            .pop_if(|frag| matches!(frag, TextFragment::HardBreak))
+            .is_none()
+        {
+            return;
+        }
+        if let Some(TextFragment::Stylised(style, s)) = self.content.last_mut() {
+            if *style == Style::Modifier && s == "\\" {
+                *style = Style::Normal;
+            }
+        }
+    }
+
+    pub(crate) fn join(&mut self, mut text: Text<'a>, separator: &'a str) {
         self.content
-            .push(TextFragment::Stylised(Style::Normal, "\n"));
+            .push(TextFragment::Stylised(Style::Normal, separator.into()));
         self.content.append(&mut text.content);
     }
 
-    pub fn style(&mut self, prefixe_len: usize, mut span: Span, style: Style) {
+    // `span` goes from the opening delimiter to the start of the closing one, and may cover
+    // several fragments, e.g. `**bold `code`**` once the code is styled. They are grouped under
+    // `style`, unless the span starts or ends inside a fragment which can't be split.
+    pub fn style(&mut self, prefixe_len: usize, span: Span, style: Style) {
+        let start = span.offset;
+        let end = span.offset + span.length + prefixe_len;
+        if span.length < prefixe_len {
+            return;
+        }
+
+        for idx in [start, start + prefixe_len, end - prefixe_len, end] {
+            self.split_at(idx);
+        }
+
+        let mut offset = 0;
+        let mut covered = self.content.len()..0;
+        let mut covered_len = 0;
+        for (idx, text_fragment) in self.content.iter().enumerate() {
+            let len = text_fragment.len();
+            if start <= offset && offset + len <= end && offset < end {
+                covered.start = covered.start.min(idx);
+                covered.end = idx + 1;
+                covered_len += len;
+            }
+            offset += len;
+        }
+        if covered_len != end - start {
+            return;
+        }
+
+        let mut offset = start;
+        let mut content: Vec<_> = self.content.drain(covered.clone()).collect();
+        for text_fragment in content.iter_mut() {
+            let len = text_fragment.len();
+            if offset < start + prefixe_len || offset >= end - prefixe_len {
+                if let TextFragment::Stylised(fragment_style, _) = text_fragment {
+                    *fragment_style = Style::Modifier;
+                }
+            }
+            offset += len;
+        }
+        self.content
+            .insert(covered.start, TextFragment::Group(style, Text { content }));
+    }
+
+    // Splits the fragment containing `idx` so that a fragment starts at `idx`
+    fn split_at(&mut self, idx: usize) {
+        let mut span = Span::new(idx, 0);
+        let Some(frag_idx) = self.find_modified_fragment(&mut span) else {
+            return;
+        };
+        if span.offset == 0 {
+            return;
+        }
+
+        match &mut self.content[frag_idx] {
+            TextFragment::Stylised(style, s) if s.is_char_boundary(span.offset) => {
+                let style = *style;
+                let (left, right) = split_cow(core::mem::take(s), span.offset);
+                self.content[frag_idx] = TextFragment::Stylised(style, left);
+                self.content
+                    .insert(frag_idx + 1, TextFragment::Stylised(style, right));
+            }
+            _ => (),
+        }
+    }
+
+    pub fn replace(&mut self, mut span: Span, frag: TextFragment<'a>) {
         let modified_fragment = self.find_modified_fragment(&mut span);
 
         if let Some(idx) = modified_fragment {
             let text_fragment = self.content.remove(idx);
-            let new_fragment = text_fragment.style_in(span, prefixe_len, style);
+            let new_fragment = text_fragment.replace(span, frag);
             for text_fragment in new_fragment.into_iter().rev() {
                 self.content.insert(idx, text_fragment)
             }
         }
     }
 
-    pub fn replace(&mut self, mut span: Span, frag: TextFragment<'a>) {
+    pub fn remove(&mut self, mut span: Span) {
         let modified_fragment = self.find_modified_fragment(&mut span);
 
         if let Some(idx) = modified_fragment {
             let text_fragment = self.content.remove(idx);
-            let new_fragment = text_fragment.replace(span, frag);
+            let new_fragment = text_fragment.remove(span);
             for text_fragment in new_fragment.into_iter().rev() {
                 self.content.insert(idx, text_fragment)
             }
         }
     }
 
-    pub fn remove(&mut self, mut span: Span) {
+    /// Puts `s` in place of the text under `span`, keeping its style.
+    pub fn substitute(&mut self, mut span: Span, s: String) {
         let modified_fragment = self.find_modified_fragment(&mut span);
 
         if let Some(idx) = modified_fragment {
             let text_fragment = self.content.remove(idx);
-            let new_fragment = text_fragment.remove(span);
+            let new_fragment = text_fragment.substitute(span, s);
             for text_fragment in new_fragment.into_iter().rev() {
                 self.content.insert(idx, text_fragment)
             }
         }
     }
 
+    // The edits, sorted by offset, are made in a single pass over the fragments, so that a line
+    // full of links costs no more per link than one with a single link. An edit crossing fragments
+    // is left out, as `replace` does.
+    pub(crate) fn edit(&mut self, edits: Vec<(Span, Edit<'a>)>) {
+        self.edit_from(0, &mut edits.into_iter().peekable());
+    }
+
+    // `offset` is where the text starts in the line the edits were found in, and where it ends is
+    // returned, rather than measuring each group nested in another one again
+    fn edit_from(
+        &mut self,
+        mut offset: usize,
+        edits: &mut Peekable<impl Iterator<Item = (Span, Edit<'a>)>>,
+    ) -> usize {
+        let mut content = Vec::with_capacity(self.content.len());
+
+        for frag in core::mem::take(&mut self.content) {
+            let (style, mut rest) = match frag {
+                TextFragment::Stylised(style, s) => (style, s),
+                TextFragment::Group(style, mut text) => {
+                    offset = text.edit_from(offset, edits);
+                    content.push(TextFragment::Group(style, text));
+                    continue;
+                }
+                frag => {
+                    let end = offset + frag.len();
+                    while edits.next_if(|(span, _)| span.offset < end).is_some() {}
+                    content.push(frag);
+                    offset = end;
+                    continue;
+                }
+            };
+
+            // `rest` is what is left of the fragment from `offset` on
+            let end = offset + rest.len();
+            while let Some((span, edit)) = edits.next_if(|(span, _)| span.offset < end) {
+                if span.offset < offset || span.offset + span.length > end {
+                    continue;
+                }
+                let (left, s) = split_cow(rest, span.offset - offset);
+                let (_, right) = split_cow(s, span.length);
+                content.push(TextFragment::Stylised(style, left));
+                match edit {
+                    Edit::Replace(frag) => content.push(frag.styled_as(style)),
+                    Edit::Remove => (),
+                    Edit::Decode(c) => {
+                        content.push(TextFragment::Stylised(style, c.to_string().into()))
+                    }
+                }
+                rest = right;
+                offset = span.offset + span.length;
+            }
+            content.push(TextFragment::Stylised(style, rest));
+            offset = end;
+        }
+
+        self.content = content;
+        offset
+    }
+
     fn find_modified_fragment(&mut self, span: &mut Span) -> Option<usize> {
         let mut offset = 0;
         let mut replaced_fragment = None;
@@ -155,115 +826,332 @@ impl<'a> Text<'a> {
     }
 }
 
-#[derive(Debug)]
+// Outermost first, so that a group styled both ways nests as `<em><strong>both</strong></em>`
+const STYLES_NESTING: [Style; 7] = [
+    Style::Strikethrough,
+    Style::Highlight,
+    Style::Sub,
+    Style::Sup,
+    Style::Code,
+    Style::Emphasis,
+    Style::Strong,
+];
+
+fn inlines<'t>(content: &'t [TextFragment]) -> Vec<Inline<'t>> {
+    let mut inlines = Vec::with_capacity(content.len());
+    for frag in content {
+        let (style, inline) = match frag {
+            TextFragment::Stylised(style, s) if style.contains(Style::Modifier) || s.is_empty() => {
+                continue
+            }
+            TextFragment::Stylised(style, s) => (*style, vec![Inline::Text(s)]),
+            TextFragment::Group(style, text) => (*style, self::inlines(&text.content)),
+            TextFragment::Link(alt, link, title) => (
+                Style::Normal,
+                vec![Inline::Link(
+                    self::inlines(&alt.content),
+                    link,
+                    title.as_deref(),
+                )],
+            ),
+            TextFragment::Image(alt, path, title, size) => (
+                Style::Normal,
+                vec![Inline::Image(alt, path, title.as_deref(), *size)],
+            ),
+            TextFragment::FootnoteRef(_, number) => {
+                (Style::Normal, vec![Inline::FootnoteRef(*number)])
+            }
+            TextFragment::HardBreak => (Style::Normal, vec![Inline::HardBreak]),
+        };
+        inlines.extend(grouped(style, inline));
+    }
+    inlines
+}
+
+// Wraps the inlines in a group for each of the styles
+fn grouped(style: Style, inlines: Vec<Inline>) -> Vec<Inline> {
+    STYLES_NESTING
+        .iter()
+        .rev()
+        .filter(|nesting| style.contains(**nesting))
+        .fold(inlines, |inlines, nesting| {
+            vec![Inline::Group(*nesting, inlines)]
+        })
+}
+
+// What `Text::edit` puts in place of the text under a span
+pub(crate) enum Edit<'a> {
+    Replace(TextFragment<'a>),
+    Remove,
+    Decode(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inline<'t> {
+    Text(&'t str),
+    Group(Style, Vec<Inline<'t>>),
+    Link(Vec<Inline<'t>>, &'t str, Option<&'t str>), // alt, link, title
+    Image(&'t str, &'t str, Option<&'t str>, ImageSize), // alt, path, title, size
+    FootnoteRef(usize),                              // number
+    HardBreak,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextFragment<'a> {
-    Stylised(Style, &'a str),
-    Link(&'a str, &'a str),  // alt, link
-    Image(&'a str, &'a str), // alt, path
+    Stylised(Style, Cow<'a, str>),
+    Group(Style, Text<'a>), // the styled text, its delimiters included as modifiers
+    Link(Text<'a>, Cow<'a, str>, Option<Cow<'a, str>>), // alt, link, title
+    Image(Cow<'a, str>, Cow<'a, str>, Option<Cow<'a, str>>, ImageSize), // alt, path, title, size
+    FootnoteRef(Cow<'a, str>, usize), // label, number
+    HardBreak,              // two trailing spaces or a backslash ending the line
 }
 
 impl<'a> Default for TextFragment<'a> {
     fn default() -> Self {
-        Self::Stylised(Style::Normal, "")
+        Self::Stylised(Style::Normal, Cow::Borrowed(""))
     }
 }
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq)]
-    pub struct Style: u8 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Style: u16 {
         const Normal = 0b00000001;
         const Strong = 0b00000010;
         const Emphasis = 0b00000100;
         const Code = 0b00001000;
         const Strikethrough = 0b00010000;
+        const Highlight = 0b01000000;
+        const Sub = 0b10000000;
+        const Sup = 0b100000000;
 
         const Modifier = 0b00100000;
     }
 }
 
 impl<'a> TextFragment<'a> {
+    pub fn into_owned(self) -> TextFragment<'static> {
+        use TextFragment::*;
+        match self {
+            Stylised(style, s) => Stylised(style, into_owned(s)),
+            Group(style, text) => Group(style, text.into_owned()),
+            Link(alt, link, title) => {
+                Link(alt.into_owned(), into_owned(link), title.map(into_owned))
+            }
+            Image(alt, path, title, size) => Image(
+                into_owned(alt),
+                into_owned(path),
+                title.map(into_owned),
+                size,
+            ),
+            FootnoteRef(label, number) => FootnoteRef(into_owned(label), number),
+            HardBreak => HardBreak,
+        }
+    }
+
+    /// Length in bytes of the markdown the fragment was parsed from.
     pub fn len(&self) -> usize {
         use TextFragment::*;
         match self {
-            Stylised(_, s) => s.chars().count(),
-            Link(alt, link) | Image(alt, link) => alt.chars().count() + link.len(),
+            Stylised(_, s) => s.len(),
+            Group(_, text) => text.len(),
+            Link(alt, link, title) => {
+                "[".len() + alt.len() + "](".len() + link.len() + title_len(title) + ")".len()
+            }
+            Image(alt, path, title, size) => {
+                "![".len()
+                    + alt.len()
+                    + "](".len()
+                    + path.len()
+                    + size.len()
+                    + title_len(title)
+                    + ")".len()
+            }
+            FootnoteRef(label, _) => "[^".len() + label.len() + "]".len(),
+            // Added once its line is parsed, it never sits among the offsets of the line
+            HardBreak => 0,
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn style_in(self, span: Span, prefixe_len: usize, style: Style) -> Vec<Self> {
-        if let Self::Stylised(initial_style, s) = &self {
-            if span.offset + span.length > s.len() {
-                return vec![self];
-            }
+        match self {
+            Self::Stylised(initial_style, s) => {
+                if span.offset + span.length > s.len() {
+                    return vec![Self::Stylised(initial_style, s)];
+                }
 
-            let (left_part, s) = s.split_at(span.offset);
-            let (left_modifier, s) = s.split_at(prefixe_len);
+                let (left_part, s) = split_cow(s, span.offset);
+                let (left_modifier, s) = split_cow(s, prefixe_len);
 
-            let (middle_part, s) = s.split_at(span.length - prefixe_len);
-            let (right_modifier, right_part) = s.split_at(prefixe_len);
+                let (middle_part, s) = split_cow(s, span.length - prefixe_len);
+                let (right_modifier, right_part) = split_cow(s, prefixe_len);
 
-            let mut texts = Vec::with_capacity(3);
-            if !left_part.is_empty() {
-                texts.push(Self::Stylised(*initial_style, left_part))
-            }
-            if !left_modifier.is_empty() {
-                texts.push(Self::Stylised(Style::Modifier, left_modifier))
-            }
-            if !middle_part.is_empty() {
-                texts.push(Self::Stylised(*initial_style | style, middle_part))
-            }
-            if !right_modifier.is_empty() {
-                texts.push(Self::Stylised(Style::Modifier, right_modifier))
-            }
-            if !right_part.is_empty() {
-                texts.push(Self::Stylised(*initial_style, right_part))
-            }
+                let mut texts = Vec::with_capacity(3);
+                if !left_part.is_empty() {
+                    texts.push(Self::Stylised(initial_style, left_part))
+                }
+                if !left_modifier.is_empty() {
+                    texts.push(Self::Stylised(Style::Modifier, left_modifier))
+                }
+                if !middle_part.is_empty() {
+                    texts.push(Self::Stylised(initial_style | style, middle_part))
+                }
+                if !right_modifier.is_empty() {
+                    texts.push(Self::Stylised(Style::Modifier, right_modifier))
+                }
+                if !right_part.is_empty() {
+                    texts.push(Self::Stylised(initial_style, right_part))
+                }
 
-            texts
-        } else {
-            panic!("Try to style unstylasible TextFormat with {style:?} in {self:?}")
+                texts
+            }
+            frag => panic!("Try to style unstylasible TextFormat with {style:?} in {frag:?}"),
         }
     }
 
     fn replace(self, span: Span, frag: TextFragment<'a>) -> Vec<Self> {
-        if let Self::Stylised(initial_style, s) = &self {
-            if span.offset + span.length > s.len() {
-                return vec![self];
-            }
+        match self {
+            Self::Stylised(initial_style, s) => {
+                if span.offset + span.length > s.len() {
+                    return vec![Self::Stylised(initial_style, s)];
+                }
 
-            let (left_part, s) = s.split_at(span.offset);
-            let (_, right_part) = s.split_at(span.length);
+                let (left_part, s) = split_cow(s, span.offset);
+                let (_, right_part) = split_cow(s, span.length);
 
-            vec![
-                Self::Stylised(*initial_style, left_part),
-                frag,
-                Self::Stylised(*initial_style, right_part),
-            ]
-        } else {
-            panic!("Try to replace unreplacable TextFormat with {frag:?} in {self:?}")
+                vec![
+                    Self::Stylised(initial_style, left_part),
+                    frag.styled_as(initial_style),
+                    Self::Stylised(initial_style, right_part),
+                ]
+            }
+            other => panic!("Try to replace unreplacable TextFormat with {frag:?} in {other:?}"),
+        }
+    }
+
+    // A link takes on the styles of the text around it
+    fn styled_as(self, around: Style) -> Self {
+        match self {
+            Self::Link(mut alt, link, title) => {
+                for alt_frag in alt.content.iter_mut() {
+                    match alt_frag {
+                        Self::Stylised(style, _) if !style.contains(Style::Modifier) => {
+                            *style |= around
+                        }
+                        _ => (),
+                    }
+                }
+                Self::Link(alt, link, title)
+            }
+            frag => frag,
         }
     }
 
     fn remove(self, span: Span) -> Vec<Self> {
-        if let Self::Stylised(initial_style, s) = &self {
-            if span.offset + span.length > s.len() {
-                return vec![self];
+        match self {
+            Self::Stylised(initial_style, s) => {
+                if span.offset + span.length > s.len() {
+                    return vec![Self::Stylised(initial_style, s)];
+                }
+
+                let (left_part, s) = split_cow(s, span.offset);
+                let (_, right_part) = split_cow(s, span.length);
+
+                vec![
+                    Self::Stylised(initial_style, left_part),
+                    Self::Stylised(initial_style, right_part),
+                ]
+            }
+            _ => panic!("Try to remove unexisting text"),
+        }
+    }
+
+    fn substitute(self, span: Span, with: String) -> Vec<Self> {
+        match self {
+            Self::Stylised(initial_style, s) => {
+                if span.offset + span.length > s.len() {
+                    return vec![Self::Stylised(initial_style, s)];
+                }
+
+                let (left_part, s) = split_cow(s, span.offset);
+                let (_, right_part) = split_cow(s, span.length);
+
+                vec![
+                    Self::Stylised(initial_style, left_part),
+                    Self::Stylised(initial_style, Cow::Owned(with)),
+                    Self::Stylised(initial_style, right_part),
+                ]
             }
+            _ => panic!("Try to substitute unexisting text"),
+        }
+    }
+}
+
+fn split_cow(s: Cow<'_, str>, mid: usize) -> (Cow<'_, str>, Cow<'_, str>) {
+    match s {
+        Cow::Borrowed(s) => {
+            let (left, right) = s.split_at(mid);
+            (Cow::Borrowed(left), Cow::Borrowed(right))
+        }
+        Cow::Owned(mut left) => {
+            let right = left.split_off(mid);
+            (Cow::Owned(left), Cow::Owned(right))
+        }
+    }
+}
+
+// As ` "title"`
+fn title_len(title: &Option<Cow<'_, str>>) -> usize {
+    title
+        .as_ref()
+        .map_or(0, |title| " \"\"".len() + title.len())
+}
 
-            let (left_part, s) = s.split_at(span.offset);
-            let (_, right_part) = s.split_at(span.length);
+/// Size given to an image by `=WxH` after its path, as `=200x100`, `=200x` or `=x100`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageSize {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ImageSize {
+    pub fn is_empty(&self) -> bool {
+        self.width.is_none() && self.height.is_none()
+    }
 
-            vec![
-                Self::Stylised(*initial_style, left_part),
-                Self::Stylised(*initial_style, right_part),
-            ]
+    fn len(&self) -> usize {
+        let digits = |dimension: Option<u32>| dimension.map_or(0, |n| n.to_string().len());
+        if self.is_empty() {
+            0
         } else {
-            panic!("Try to remove unexisting text")
+            " =x".len() + digits(self.width) + digits(self.height)
+        }
+    }
+}
+
+impl fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("=")?;
+        if let Some(width) = self.width {
+            f.write_fmt(format_args!("{width}"))?;
+        }
+        f.write_str("x")?;
+        if let Some(height) = self.height {
+            f.write_fmt(format_args!("{height}"))?;
         }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+/// A range of the source, in bytes so it can be sliced directly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     pub offset: usize,
     pub length: usize,
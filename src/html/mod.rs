@@ -1,130 +1,761 @@
-use std::fmt;
+mod options;
+mod output;
+#[cfg(test)]
+mod tests;
+
+use alloc::{borrow::Cow, collections::BTreeSet, format, string::String, vec, vec::Vec};
+use core::fmt;
 
 use crate::{
-    document::{Document, ListKind, Node, Style, Text, TextFragment},
-    md::MarkDown,
+    document::{Alignment, CodeBlock, Document, Inline, ListKind, Node, Style, Text, TextFragment},
+    md::{MarkDown, ParserOptions},
+    render::Renderer,
 };
 
-pub struct HTML<'a>(pub Document<'a>);
+pub use options::{ContainerTag, HtmlOptions, LineBreakPolicy, LineEnding, VideoEmbed};
+pub use output::{Heading, RenderOutput, TocEntry};
+
+#[must_use]
+pub struct HTML<'a>(pub Document<'a>, pub HtmlOptions);
+
+impl<'a> HTML<'a> {
+    pub fn with_options(mut self, options: HtmlOptions) -> Self {
+        self.1 = options;
+        self
+    }
+
+    /// Appends the rendered document to `out`, leaving its previous content untouched.
+    pub fn write_html(&self, out: &mut String) {
+        out.reserve(estimated_len(&self.0.nodes));
+        self.render_to(out)
+            .expect("rendering into a String cannot fail")
+    }
+
+    /// Renders the document into a `String` allocated up front for its estimated size.
+    pub fn to_string_with_capacity(&self) -> String {
+        let mut out = String::new();
+        self.write_html(&mut out);
+        out
+    }
+
+    /// Writes the rendered document straight into `w`, without building it in a `String` first.
+    pub fn render_to<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        self.0.render(&mut HtmlRenderer::new(&self.1, w))
+    }
+}
 
 impl<'a> From<MarkDown<'a>> for HTML<'a> {
     fn from(markdown: MarkDown<'a>) -> Self {
-        Self(markdown.0)
+        Self(markdown.0, HtmlOptions::default())
     }
 }
 
 impl<'a> fmt::Display for HTML<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut lists: Vec<&ListKind> = Vec::new();
+        self.render_to(f)
+    }
+}
 
-        for line in self.0.nodes.iter() {
-            let mut reset_list = true;
-            match line {
-                Node::Header(level, text) => {
-                    f.write_fmt(format_args!("<h{level}>{}</h{level}>", text_to_htlm(text)))?
-                }
-                Node::Paragraphe(text) => f.write_fmt(format_args!(
-                    "<p>{}</p>",
-                    text_to_htlm(text).replace('\n', "<br>")
-                ))?,
-                Node::CodeBlock(codeblock) => f.write_fmt(format_args!(
-                    "<pre><code class={}>{}</code></pre>",
-                    codeblock.language,
-                    codeblock.fetch().unwrap()
-                ))?,
-                Node::List(list_kind, text) => {
-                    reset_list = false;
-                    let deepth_delta = lists
-                        .last()
-                        .map(|pre_list| list_kind.deepth() as isize - pre_list.deepth() as isize)
-                        .unwrap_or(1);
-
-                    match deepth_delta {
-                        0 => f.write_fmt(format_args!("<li>{}</li>", text_to_htlm(text)))?,
-                        d if d > 0 => {
-                            for _ in 0..d {
-                                lists.push(list_kind);
-                                init_list_html(list_kind, f)?;
-                            }
-                            f.write_fmt(format_args!("<li>{}</li>", text_to_htlm(text)))?;
-                        }
-                        d if d < 0 => {
-                            for _ in 0..d.abs() {
-                                let pre_list = lists.pop().unwrap();
-                                end_list_html(pre_list, f)?;
-                            }
-                            f.write_fmt(format_args!("<li>{}</li>", text_to_htlm(text)))?;
-                        }
-                        _ => unreachable!(),
-                    }
+/// Parses `input` and renders it with the default options.
+///
+/// ```
+/// assert_eq!(htmd::to_html("# Hi *you*"), "<h1>Hi <em>you</em></h1>");
+/// ```
+pub fn to_html(input: &str) -> String {
+    to_html_with(input, &ParserOptions::default())
+}
+
+pub fn to_html_with(input: &str, options: &ParserOptions) -> String {
+    HTML::from(MarkDown::parse_with(input, options)).to_string_with_capacity()
+}
+
+/// The HTML output, as `HTML` renders it with its own options.
+pub struct HtmlRenderer<'r, W> {
+    options: &'r HtmlOptions,
+    output: Option<&'r mut RenderOutput>,
+    f: &'r mut W,
+    slugs: BTreeSet<String>,
+    // A nested list just ended, the end of the item holding it going on its own line
+    after_nested_list: bool,
+}
+
+impl<'r, W: fmt::Write> HtmlRenderer<'r, W> {
+    pub fn new(options: &'r HtmlOptions, f: &'r mut W) -> Self {
+        Self {
+            options,
+            output: None,
+            f,
+            slugs: BTreeSet::new(),
+            after_nested_list: false,
+        }
+    }
+}
+
+impl<'r, W: fmt::Write> Renderer for HtmlRenderer<'r, W> {
+    fn node(&mut self, node: &Node) {
+        if let Some(output) = self.output.as_deref_mut() {
+            output.collect_node(node, self.options);
+        }
+    }
+
+    fn header(&mut self, level: usize, text: &Text) -> fmt::Result {
+        let level = self.options.heading_level(level);
+        self.f.write_fmt(format_args!("<h{level}"))?;
+        if self.options.heading_ids {
+            let slug = unique_slug(text.slug(), |slug| self.slugs.contains(slug));
+            if !slug.is_empty() {
+                self.f.write_fmt(format_args!(" id=\"{slug}\""))?;
+            }
+            self.slugs.insert(slug);
+        }
+        self.f.write_str(">")?;
+        self.text(text)?;
+        self.f.write_fmt(format_args!("</h{level}>"))?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn paragraph(&mut self, text: &Text) -> fmt::Result {
+        match video_embed(text, self.options) {
+            Some(embed) => self.f.write_str(&embed)?,
+            None => match lone_image(text).filter(|_| self.options.image_figures) {
+                Some(image) => write_figure(image, self.options, self.f)?,
+                None => write_paragraphe(text, self.options, self.f)?,
+            },
+        }
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn quote_start(&mut self, _deepth: usize) -> fmt::Result {
+        self.f.write_str("<blockquote>")?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn quote(&mut self, _deepth: usize, text: &Text) -> fmt::Result {
+        write_paragraphe(text, self.options, self.f)?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn quote_end(&mut self, _deepth: usize) -> fmt::Result {
+        self.f.write_str("</blockquote>")?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn list_start(&mut self, kind: &ListKind, deepth: usize, items: &[Node]) -> fmt::Result {
+        if self.options.task_progress && deepth == 0 {
+            write_task_progress(items, self.options, self.f)?;
+        }
+        // Nested in an item, a list starts on the line after its text
+        if deepth > 0 {
+            write_pretty_newline(self.options, self.f)?;
+        }
+        init_list_html(kind, self.options, deepth, self.f)
+    }
+
+    fn list_item(
+        &mut self,
+        kind: &ListKind,
+        deepth: usize,
+        paragraphs: &[Text],
+        loose: bool,
+    ) -> fmt::Result {
+        write_list_item(kind, paragraphs, loose, self.options, deepth, self.f)
+    }
+
+    fn list_item_end(&mut self, _kind: &ListKind, deepth: usize) -> fmt::Result {
+        if self.after_nested_list {
+            write_pretty_indent(self.options, 2 * deepth - 1, self.f)?;
+            self.after_nested_list = false;
+        }
+        self.f.write_str("</li>")?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn list_end(&mut self, kind: &ListKind, deepth: usize) -> fmt::Result {
+        self.after_nested_list = deepth > 0;
+        end_list_html(kind, self.options, deepth, self.f)
+    }
+
+    fn table(
+        &mut self,
+        header: &[Text],
+        alignments: &[Option<Alignment>],
+        rows: &[Vec<Text>],
+    ) -> fmt::Result {
+        write_table(header, alignments, rows, self.options, self.f)?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn code_block(&mut self, codeblock: &CodeBlock) -> fmt::Result {
+        write_code_block(codeblock, self.options, self.f)?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn container_start(&mut self, name: &str, attrs: &[(Cow<str>, Cow<str>)]) -> fmt::Result {
+        match self.options.containers.get(name) {
+            Some(tag) => {
+                self.f.write_fmt(format_args!("<{}", tag.element))?;
+                for (attr, value) in tag.attributes.iter() {
+                    write_attribute(attr, value, self.options, self.f)?;
                 }
-                Node::LineBreak => f.write_str("<br>")?,
-                Node::Rule => f.write_str("<hr>")?,
             }
+            None => self
+                .f
+                .write_fmt(format_args!("<div class=\"{}\"", escape_html(name)))?,
+        }
+        for (attr, value) in attrs {
+            write_attribute(attr, value, self.options, self.f)?;
+        }
+        self.f.write_str(">")?;
+        write_pretty_newline(self.options, self.f)
+    }
 
-            if reset_list {
-                for pre_list in lists.drain(..).rev() {
-                    end_list_html(pre_list, f)?;
+    fn container_end(&mut self, name: &str) -> fmt::Result {
+        let element = match self.options.containers.get(name) {
+            Some(tag) => tag.element.as_str(),
+            None => "div",
+        };
+        self.f.write_fmt(format_args!("</{element}>"))?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn line_break(&mut self) -> fmt::Result {
+        match self.options.line_breaks {
+            LineBreakPolicy::Drop => return Ok(()),
+            LineBreakPolicy::Br => self
+                .f
+                .write_fmt(format_args!("<br{}", self.options.void_end()))?,
+            LineBreakPolicy::ParagraphGap => self.f.write_str("<p></p>")?,
+        }
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn rule(&mut self) -> fmt::Result {
+        self.f
+            .write_fmt(format_args!("<hr{}", self.options.void_end()))?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn footnotes_start(&mut self) -> fmt::Result {
+        self.f.write_str("<section class=\"footnotes\"><ol>")?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn footnote(&mut self, number: usize, text: &Text) -> fmt::Result {
+        self.f.write_fmt(format_args!("<li id=\"fn-{number}\">"))?;
+        self.text(text)?;
+        self.f.write_fmt(format_args!(
+            " <a href=\"#fnref-{number}\">\u{21a9}</a></li>"
+        ))?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn footnotes_end(&mut self) -> fmt::Result {
+        self.f.write_str("</ol></section>")?;
+        write_pretty_newline(self.options, self.f)
+    }
+
+    fn text_fragment(&mut self, fragment: &TextFragment) -> fmt::Result {
+        self.text(&Text {
+            content: vec![fragment.clone()],
+        })
+    }
+
+    // Styles span several fragments, so they are written from the whole text at once
+    fn text(&mut self, text: &Text) -> fmt::Result {
+        write_text(text, self.options, self.f)
+    }
+}
+
+// The first free one of `slug`, `slug-1`, `slug-2`...
+fn unique_slug(slug: String, taken: impl Fn(&str) -> bool) -> String {
+    let mut unique = slug.clone();
+    let mut n = 0;
+    while taken(&unique) {
+        n += 1;
+        unique = format!("{slug}-{n}");
+    }
+    unique
+}
+
+fn video_embed(text: &Text, options: &HtmlOptions) -> Option<String> {
+    if options.video_embeds.is_empty() {
+        return None;
+    }
+
+    let mut visible = text.content.iter().filter(|frag| match frag {
+        TextFragment::Stylised(_, s) => !s.trim().is_empty(),
+        _ => true,
+    });
+    let url = match (visible.next()?, visible.next()) {
+        (TextFragment::Stylised(Style::Normal, s), None)
+            if !s.trim().contains(char::is_whitespace) =>
+        {
+            s.trim()
+        }
+        (TextFragment::Link(_, link, _), None) => link,
+        _ => return None,
+    };
+
+    options
+        .video_embeds
+        .iter()
+        .find_map(|embed| embed.embed(url))
+}
+
+fn lone_image<'t, 'a>(text: &'t Text<'a>) -> Option<&'t TextFragment<'a>> {
+    let mut visible = text.content.iter().filter(|frag| match frag {
+        TextFragment::Stylised(_, s) => !s.trim().is_empty(),
+        _ => true,
+    });
+    match (visible.next()?, visible.next()) {
+        (image @ TextFragment::Image(_, _, _, _), None) => Some(image),
+        _ => None,
+    }
+}
+
+fn write_figure(
+    image: &TextFragment,
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    f.write_str("<figure>")?;
+    write_text(
+        &Text {
+            content: vec![image.clone()],
+        },
+        options,
+        f,
+    )?;
+    if let TextFragment::Image(alt, _, _, _) = image {
+        if !alt.is_empty() {
+            f.write_fmt(format_args!(
+                "<figcaption>{}</figcaption>",
+                escape_alt(alt, options)
+            ))?;
+        }
+    }
+    f.write_str("</figure>")
+}
+
+fn write_code_block(
+    codeblock: &CodeBlock,
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    // Code is always shown as written, never interpreted as HTML, and a span out of its
+    // source renders an empty block rather than panicking inside `Display`
+    let code = escape_code(codeblock.fetch().unwrap_or_default());
+    let code = if options.escape_braces && options.escape_braces_in_code {
+        Cow::Owned(escape_braces(&code).into_owned())
+    } else {
+        code
+    };
+
+    let language = escape_html(&codeblock.language);
+    if options.show_code_language && !language.is_empty() {
+        f.write_fmt(format_args!("<div class=\"code-lang\">{language}</div>"))?;
+    }
+    if language.is_empty() {
+        f.write_str("<pre><code>")?
+    } else {
+        f.write_fmt(format_args!("<pre><code class=\"language-{language}\">"))?
+    }
+    if options.code_line_numbers {
+        for (number, line) in (codeblock.line_start..).zip(code.lines()) {
+            f.write_fmt(format_args!(
+                "<span class=\"line\" data-line=\"{number}\">{line}</span>\n"
+            ))?;
+        }
+    } else {
+        f.write_str(&code)?;
+    }
+    f.write_str("</code></pre>")
+}
+
+fn write_table(
+    header: &[Text],
+    alignments: &[Option<Alignment>],
+    rows: &[Vec<Text>],
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    f.write_str("<table><thead>")?;
+    write_table_row(header, alignments, "th", options, f)?;
+    f.write_str("</thead>")?;
+    if !rows.is_empty() {
+        f.write_str("<tbody>")?;
+        for row in rows {
+            write_table_row(row, alignments, "td", options, f)?;
+        }
+        f.write_str("</tbody>")?;
+    }
+    f.write_str("</table>")
+}
+
+fn write_table_row(
+    row: &[Text],
+    alignments: &[Option<Alignment>],
+    tag: &str,
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    f.write_str("<tr>")?;
+    for (cell, alignment) in row.iter().zip(alignments) {
+        let align = match alignment {
+            Some(Alignment::Left) => " style=\"text-align: left\"",
+            Some(Alignment::Center) => " style=\"text-align: center\"",
+            Some(Alignment::Right) => " style=\"text-align: right\"",
+            None => "",
+        };
+        f.write_fmt(format_args!("<{tag}{align}>"))?;
+        write_text(cell, options, f)?;
+        f.write_fmt(format_args!("</{tag}>"))?;
+    }
+    f.write_str("</tr>")
+}
+
+// Attributes are written from the markdown, so names which could break out of the tag are left out
+// along event handlers in safe mode, and values are escaped
+fn write_attribute(
+    attr: &str,
+    value: &str,
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    let plain_name = !attr.is_empty()
+        && attr
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let handler = attr
+        .get(..2)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("on"));
+    if !plain_name || (options.safe && handler) {
+        return Ok(());
+    }
+
+    if value.is_empty() {
+        f.write_fmt(format_args!(" {attr}"))
+    } else {
+        f.write_fmt(format_args!(" {attr}=\"{}\"", escape_html(value)))
+    }
+}
+
+fn write_list_item(
+    list_kind: &ListKind,
+    paragraphs: &[Text],
+    loose: bool,
+    options: &HtmlOptions,
+    deepth: usize,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    write_pretty_indent(options, 2 * deepth - 1, f)?;
+    match list_kind {
+        ListKind::Task(_, checked) => {
+            // XHTML has no attribute left without a value
+            let (disabled, checked) = match (options.xhtml, checked) {
+                (false, true) => (" disabled", " checked"),
+                (false, false) => (" disabled", ""),
+                (true, true) => (" disabled=\"disabled\"", " checked=\"checked\""),
+                (true, false) => (" disabled=\"disabled\"", ""),
+            };
+            f.write_fmt(format_args!(
+                "<li><input type=\"checkbox\"{disabled}{checked}{} ",
+                options.void_end()
+            ))?
+        }
+        _ => f.write_str("<li>")?,
+    }
+    match paragraphs {
+        [text] if !loose => write_text(text, options, f)?,
+        paragraphs => {
+            for text in paragraphs {
+                f.write_str("<p>")?;
+                write_text(text, options, f)?;
+                f.write_str("</p>")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_task_progress(
+    nodes: &[Node],
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    let (mut checked, mut total) = (0, 0);
+    for node in nodes {
+        match node {
+            Node::List(ListKind::Task(_, is_checked), _, _) => {
+                total += 1;
+                if *is_checked {
+                    checked += 1;
                 }
             }
+            Node::List(_, _, _) => (),
+            _ => break,
         }
+    }
 
-        Ok(())
+    if total > 0 {
+        f.write_fmt(format_args!(
+            "<div class=\"task-progress\">{checked}/{total}</div>"
+        ))?;
+        write_pretty_newline(options, f)?;
     }
+    Ok(())
 }
 
-fn init_list_html(list_kind: &ListKind, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+fn init_list_html(
+    list_kind: &ListKind,
+    options: &HtmlOptions,
+    deepth: usize,
+    f: &mut impl fmt::Write,
+) -> Result<(), fmt::Error> {
+    write_pretty_indent(options, 2 * deepth, f)?;
     match list_kind {
-        ListKind::Oredred(_) => {
+        ListKind::Oredred(_, _) => {
             f.write_str("<ol>")?;
         }
-        ListKind::Unordere(_) => {
+        ListKind::Unordere(_) | ListKind::Task(_, _) => {
             f.write_str("<ul>")?;
         }
     };
-    Ok(())
+    write_pretty_newline(options, f)
 }
 
-fn end_list_html(list_kind: &ListKind, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+fn end_list_html(
+    list_kind: &ListKind,
+    options: &HtmlOptions,
+    deepth: usize,
+    f: &mut impl fmt::Write,
+) -> Result<(), fmt::Error> {
+    write_pretty_indent(options, 2 * deepth, f)?;
     match list_kind {
-        ListKind::Oredred(_) => {
+        ListKind::Oredred(_, _) => {
             f.write_str("</ol>")?;
         }
-        ListKind::Unordere(_) => {
+        ListKind::Unordere(_) | ListKind::Task(_, _) => {
             f.write_str("</ul>")?;
         }
     };
+    write_pretty_newline(options, f)
+}
+
+fn write_pretty_indent(
+    options: &HtmlOptions,
+    deepth: usize,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    match options.pretty_indent {
+        Some(width) => f.write_fmt(format_args!("{:1$}", "", width * deepth)),
+        None => Ok(()),
+    }
+}
+
+fn write_pretty_newline(options: &HtmlOptions, f: &mut impl fmt::Write) -> fmt::Result {
+    if options.pretty_indent.is_some() {
+        f.write_str(options.line_ending.as_str())?;
+    }
     Ok(())
 }
 
-fn text_to_htlm(text: &Text) -> String {
-    text.content
+const STYLE_TAGS: [(Style, &str); 7] = [
+    (Style::Strikethrough, "s"),
+    (Style::Highlight, "mark"),
+    (Style::Sub, "sub"),
+    (Style::Sup, "sup"),
+    (Style::Code, "code"),
+    (Style::Emphasis, "em"),
+    (Style::Strong, "strong"),
+];
+
+fn write_paragraphe(text: &Text, options: &HtmlOptions, f: &mut impl fmt::Write) -> fmt::Result {
+    f.write_str("<p>")?;
+    write_text(text, options, f)?;
+    f.write_str("</p>")
+}
+
+fn write_text(text: &Text, options: &HtmlOptions, f: &mut impl fmt::Write) -> fmt::Result {
+    write_inlines(&text.inlines(), false, options, f)
+}
+
+fn write_inlines(
+    inlines: &[Inline],
+    in_code: bool,
+    options: &HtmlOptions,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => {
+                let text = if in_code {
+                    escape_code(text)
+                } else {
+                    escape_html(text)
+                };
+                if options.escape_braces && (options.escape_braces_in_code || !in_code) {
+                    f.write_str(&escape_braces(&text))?
+                } else {
+                    f.write_str(&text)?
+                }
+            }
+            Inline::Group(style, children) => {
+                let tag = STYLE_TAGS
+                    .iter()
+                    .find_map(|(tag_style, tag)| (tag_style == style).then_some(*tag))
+                    .unwrap_or("span");
+                f.write_fmt(format_args!("<{tag}>"))?;
+                write_inlines(children, in_code || *style == Style::Code, options, f)?;
+                f.write_fmt(format_args!("</{tag}>"))?;
+            }
+            Inline::Link(alt, link, title) => {
+                let href = options.resolve_url(options.safe_url(link));
+                f.write_fmt(format_args!("<a href=\"{}\"", escape_html(&href)))?;
+                if options.opens_in_new_tab(link) {
+                    f.write_str(" target=\"_blank\" rel=\"noopener noreferrer\"")?;
+                }
+                write_title(*title, options, f)?;
+                f.write_str(">")?;
+                write_inlines(alt, in_code, options, f)?;
+                f.write_str("</a>")?;
+            }
+            Inline::Image(alt, src, title, size) => {
+                f.write_fmt(format_args!(
+                    "<img src=\"{}\" alt=\"{}\"",
+                    escape_html(&options.resolve_url(options.safe_url(src))),
+                    escape_alt(alt, options)
+                ))?;
+                write_title(*title, options, f)?;
+                if let Some(width) = size.width {
+                    f.write_fmt(format_args!(" width=\"{width}\""))?;
+                }
+                if let Some(height) = size.height {
+                    f.write_fmt(format_args!(" height=\"{height}\""))?;
+                }
+                if options.lazy_images {
+                    f.write_str(" loading=\"lazy\"")?;
+                }
+                f.write_str(options.void_end())?
+            }
+            Inline::FootnoteRef(number) => f.write_fmt(format_args!(
+                "<sup id=\"fnref-{number}\"><a href=\"#fn-{number}\">{number}</a></sup>"
+            ))?,
+            Inline::HardBreak => f.write_fmt(format_args!("<br{}", options.void_end()))?,
+        }
+    }
+    Ok(())
+}
+
+fn escape_alt(alt: &str, options: &HtmlOptions) -> String {
+    let alt = escape_html(alt);
+    if options.escape_braces {
+        escape_braces(&alt).into_owned()
+    } else {
+        alt.into_owned()
+    }
+}
+
+fn write_title(title: Option<&str>, options: &HtmlOptions, f: &mut impl fmt::Write) -> fmt::Result {
+    let Some(title) = title else {
+        return Ok(());
+    };
+    let title = escape_html(title);
+    let title = if options.escape_braces {
+        escape_braces(&title)
+    } else {
+        title
+    };
+    f.write_fmt(format_args!(" title=\"{title}\""))
+}
+
+// The markdown the nodes were parsed from, grown for the tags, and by an eighth on top to spare
+// a reallocation when it falls short
+fn estimated_len(nodes: &[Node]) -> usize {
+    let len: usize = nodes
         .iter()
-        .filter_map(|frag| match frag {
-            TextFragment::Stylised(styles, text) if !styles.contains(Style::Modifier) => {
-                Some(apply_styles_html(text, *styles))
+        .map(|node| match node {
+            Node::Header(_, text) | Node::Paragraphe(text) | Node::Quote(_, text) => {
+                estimated_text_len(text) + 16
+            }
+            // Each footnote gets an item and a back link in the section ending the document
+            Node::Footnote(_, text) => estimated_text_len(text) + 64,
+            Node::List(list_kind, paragraphs, _) => {
+                let checkbox = match list_kind {
+                    ListKind::Task(..) => 48,
+                    _ => 0,
+                };
+                paragraphs.iter().map(estimated_text_len).sum::<usize>()
+                    + 16 * paragraphs.len()
+                    + checkbox
             }
-            TextFragment::Link(alt, link) => Some(format!("<a href=\"{link}\">{alt}</a>")),
-            TextFragment::Image(alt, src) => Some(format!("<img src=\"{src}\" alt=\"{alt}\">")),
-            _ => None::<String>,
+            Node::Table {
+                header,
+                alignments,
+                rows,
+            } => {
+                let aligned = alignments.iter().filter(|a| a.is_some()).count();
+                let cells = header.iter().chain(rows.iter().flatten());
+                cells
+                    .map(|cell| estimated_text_len(cell) + 16)
+                    .sum::<usize>()
+                    + 24 * aligned * (rows.len() + 1)
+                    + 16 * rows.len()
+                    + 48
+            }
+            Node::CodeBlock(codeblock) => codeblock.code.length + codeblock.language.len() + 48,
+            Node::Container { children, .. } => estimated_len(children) + 32,
+            Node::LineBreak | Node::Rule => 4,
         })
-        .collect()
+        .sum();
+    len + len / 8
 }
 
-fn apply_styles_html(text: &str, styles: Style) -> String {
-    let mut text = text.to_owned();
-    if styles.contains(Style::Strong) {
-        text = format!("<strong>{text}</strong>")
-    }
-    if styles.contains(Style::Emphasis) {
-        text = format!("<em>{text}</em>")
-    }
-    if styles.contains(Style::Code) {
-        text = format!("<code>{text}</code>")
+// Delimiters and link brackets turn into tags several times longer
+fn estimated_text_len(text: &Text) -> usize {
+    text.content
+        .iter()
+        .map(|fragment| match fragment {
+            TextFragment::Stylised(style, s) if style.contains(Style::Modifier) => s.len() * 5,
+            TextFragment::Group(_, text) => estimated_text_len(text),
+            TextFragment::Link(..) | TextFragment::Image(..) => fragment.len() + 16,
+            TextFragment::FootnoteRef(..) => 48,
+            TextFragment::HardBreak => 4,
+            fragment => fragment.len(),
+        })
+        .sum()
+}
+
+fn escape_html(s: &str) -> Cow<'_, str> {
+    if s.contains(['&', '<', '>', '"']) {
+        Cow::Owned(
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;"),
+        )
+    } else {
+        Cow::Borrowed(s)
     }
-    if styles.contains(Style::Strikethrough) {
-        text = format!("<s>{text}</s>")
+}
+
+// Code is never within an attribute, leaving its quotes as they are
+fn escape_code(s: &str) -> Cow<'_, str> {
+    if s.contains(['&', '<', '>']) {
+        Cow::Owned(
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+        )
+    } else {
+        Cow::Borrowed(s)
     }
+}
 
-    text
+fn escape_braces(s: &str) -> Cow<'_, str> {
+    if s.contains(['{', '}']) {
+        Cow::Owned(s.replace('{', "&#123;").replace('}', "&#125;"))
+    } else {
+        Cow::Borrowed(s)
+    }
 }
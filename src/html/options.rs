@@ -0,0 +1,308 @@
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct HtmlOptions {
+    pub containers: BTreeMap<String, ContainerTag>,
+    /// Added to every header level, `# Title` rendering `<h2>` with an offset of 1. Levels
+    /// stop at `<h6>`.
+    pub heading_offset: usize,
+    /// Give each header an `id` slugged from its text, `-1`, `-2`... telling apart the same ones.
+    /// An HTML option rather than a `ParserOptions` one, as the document holds no ids: those of
+    /// documents built in code are slugged the same, as are the table of contents' ones.
+    pub heading_ids: bool,
+    /// Wrap each code block line in a `<span class="line" data-line="N">`.
+    pub code_line_numbers: bool,
+    /// Emit a `<div class="code-lang">lang</div>` before code blocks having a language.
+    pub show_code_language: bool,
+    /// Emit a `<div class="task-progress">checked/total</div>` before lists holding tasks.
+    pub task_progress: bool,
+    /// Escape `{` and `}` as `&#123;` and `&#125;` in text, so templating engines leave them.
+    pub escape_braces: bool,
+    /// Extend `escape_braces` to code blocks and code spans.
+    pub escape_braces_in_code: bool,
+    /// Put every block on its own line, nested lists being indented by this many spaces per level.
+    pub pretty_indent: Option<usize>,
+    /// Newline ending each block when `pretty_indent` is set.
+    pub line_ending: LineEnding,
+    /// Embeds replacing paragraphs made of a lone link to a known video.
+    pub video_embeds: Vec<VideoEmbed>,
+    /// What the blank lines separating two blocks are rendered as.
+    pub line_breaks: LineBreakPolicy,
+    /// Open `http` and `https` links in a new tab with `target="_blank" rel="noopener noreferrer"`.
+    pub external_links_new_tab: bool,
+    /// Url of the site the HTML is shown on, as `https://example.com`, whose links aren't external.
+    pub link_base: Option<String>,
+    /// Blank the urls of links and images whose scheme isn't `http`, `https` or `mailto` (e.g.
+    /// `javascript:`); relative urls and anchors are kept.
+    pub safe: bool,
+    /// Url relative links and images are resolved against, as `/blog/post/`, absolute urls and
+    /// anchors being kept.
+    pub base_url: Option<String>,
+    /// Close void elements as XHTML does, `<br/>` rather than `<br>`.
+    pub xhtml: bool,
+    /// Add `loading="lazy"` to every `<img>`.
+    pub lazy_images: bool,
+    /// Render a paragraph made of a lone image as a `<figure>`, the alt of the image being its
+    /// `<figcaption>`.
+    pub image_figures: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineBreakPolicy {
+    /// Nothing, the blocks follow each other directly.
+    Drop,
+    /// A `<br>`.
+    #[default]
+    Br,
+    /// An empty `<p></p>`, spacing the blocks like a paragraph would.
+    ParagraphGap,
+}
+
+impl HtmlOptions {
+    #[must_use]
+    pub fn container(mut self, name: impl Into<String>, tag: ContainerTag) -> Self {
+        self.containers.insert(name.into(), tag);
+        self
+    }
+
+    pub(crate) const fn void_end(&self) -> &'static str {
+        if self.xhtml {
+            "/>"
+        } else {
+            ">"
+        }
+    }
+
+    pub(crate) fn heading_level(&self, level: usize) -> usize {
+        (level + self.heading_offset).min(6)
+    }
+
+    #[must_use]
+    pub fn video_embed(mut self, embed: VideoEmbed) -> Self {
+        self.video_embeds.push(embed);
+        self
+    }
+
+    pub(crate) fn opens_in_new_tab(&self, link: &str) -> bool {
+        let Some(host) = url_host(link) else {
+            return false;
+        };
+        self.external_links_new_tab
+            && !self
+                .link_base
+                .as_deref()
+                .is_some_and(|base| url_host(base).unwrap_or(base).eq_ignore_ascii_case(host))
+    }
+
+    pub(crate) fn safe_url<'u>(&self, url: &'u str) -> &'u str {
+        if !self.safe {
+            return url;
+        }
+        // Browsers skip whitespace and control characters inside a scheme
+        let url_start: String = url
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+            .take_while(|c| !['/', '?', '#'].contains(c))
+            .collect();
+        let unsafe_scheme = url_start.split_once(':').is_some_and(|(scheme, _)| {
+            !["http", "https", "mailto"]
+                .iter()
+                .any(|safe| scheme.eq_ignore_ascii_case(safe))
+        });
+        if unsafe_scheme {
+            ""
+        } else {
+            url
+        }
+    }
+
+    pub(crate) fn resolve_url<'u>(&self, url: &'u str) -> Cow<'u, str> {
+        let Some(base) = self.base_url.as_deref() else {
+            return Cow::Borrowed(url);
+        };
+        let has_scheme = url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains(['/', '?', '#']));
+        if url.is_empty() || url.starts_with(['#', '?']) || url.starts_with("//") || has_scheme {
+            return Cow::Borrowed(url);
+        }
+
+        // `https://example.com/blog/` splits into its origin and its path
+        let origin_len = base.find("://").map_or(0, |scheme_end| {
+            let host_start = scheme_end + "://".len();
+            base[host_start..]
+                .find(['/', '?', '#'])
+                .map_or(base.len(), |host_len| host_start + host_len)
+        });
+        let (origin, base_path) = base.split_at(origin_len);
+        let base_path = &base_path[..base_path.find(['?', '#']).unwrap_or(base_path.len())];
+
+        let (path, query) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+        let path = if path.starts_with('/') {
+            Cow::Borrowed(path)
+        } else {
+            let base_dir = &base_path[..base_path.rfind('/').map_or(0, |idx| idx + 1)];
+            let base_dir = if base_dir.is_empty() && !origin.is_empty() {
+                "/"
+            } else {
+                base_dir
+            };
+            Cow::Owned(format!("{base_dir}{path}"))
+        };
+
+        Cow::Owned(format!("{origin}{}{query}", remove_dot_segments(&path)))
+    }
+}
+
+// `/a/./b/../c` becomes `/a/c`, a relative path keeping the `..` going above where it starts
+fn remove_dot_segments(path: &str) -> String {
+    let rooted = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+
+    let mut parts = path.split('/').skip(usize::from(rooted)).peekable();
+    while let Some(part) = parts.next() {
+        match part {
+            "." => {}
+            ".." => {
+                if segments.last().is_some_and(|last| *last != "..") {
+                    segments.pop();
+                } else if !rooted {
+                    segments.push("..");
+                }
+            }
+            part => segments.push(part),
+        }
+        // `a/..` is the directory `a` was in, its trailing `/` included
+        if parts.peek().is_none() && (part == "." || part == "..") {
+            segments.push("");
+        }
+    }
+
+    let path = segments.join("/");
+    if rooted {
+        format!("/{path}")
+    } else {
+        path
+    }
+}
+
+// The host, port included, of an `http` or `https` url
+fn url_host(url: &str) -> Option<&str> {
+    let rest = ["https://", "http://"].into_iter().find_map(|scheme| {
+        url.get(..scheme.len())
+            .filter(|url_scheme| url_scheme.eq_ignore_ascii_case(scheme))
+            .map(|_| &url[scheme.len()..])
+    })?;
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    authority.rsplit('@').next()
+}
+
+/// Element emitted for a `:::name` container in place of the default `<div class="name">`.
+#[derive(Debug, Clone)]
+pub struct ContainerTag {
+    pub element: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl ContainerTag {
+    #[must_use]
+    pub fn new(element: impl Into<String>) -> Self {
+        Self {
+            element: element.into(),
+            attributes: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn attribute(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Turns links starting with one of `url_prefixes`, scheme left out, into `template` where
+/// `{id}` is replaced by the rest of the link up to its first `?`, `&`, `#` or `/`.
+#[derive(Debug, Clone)]
+pub struct VideoEmbed {
+    pub url_prefixes: Vec<String>,
+    pub template: String,
+}
+
+impl VideoEmbed {
+    #[must_use]
+    pub fn new(url_prefixes: &[&str], template: impl Into<String>) -> Self {
+        Self {
+            url_prefixes: url_prefixes
+                .iter()
+                .map(|prefix| prefix.to_string())
+                .collect(),
+            template: template.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn youtube() -> Self {
+        Self::new(
+            &[
+                "www.youtube.com/watch?v=",
+                "youtube.com/watch?v=",
+                "youtu.be/",
+            ],
+            "<iframe src=\"https://www.youtube.com/embed/{id}\" allowfullscreen></iframe>",
+        )
+    }
+
+    #[must_use]
+    pub fn vimeo() -> Self {
+        Self::new(
+            &["vimeo.com/", "www.vimeo.com/"],
+            "<iframe src=\"https://player.vimeo.com/video/{id}\" allowfullscreen></iframe>",
+        )
+    }
+
+    pub(super) fn embed(&self, url: &str) -> Option<String> {
+        let url = url
+            .strip_prefix("https://")
+            .or(url.strip_prefix("http://"))
+            .unwrap_or(url);
+
+        let id = self
+            .url_prefixes
+            .iter()
+            .find_map(|prefix| url.strip_prefix(prefix.as_str()))?;
+        let id = &id[..id.find(['?', '&', '#', '/']).unwrap_or(id.len())];
+
+        // The id is written into the template as is, so only plain ids are embedded
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            None
+        } else {
+            Some(self.template.replace("{id}", id))
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt};
+
+use crate::document::{Document, Node, Text, TextFragment};
+
+use super::{unique_slug, HtmlOptions, HtmlRenderer};
+
+impl<'a> Document<'a> {
+    /// Renders the document and gathers its metadata while walking the nodes only once.
+    pub fn render_with_metadata(&self, options: &HtmlOptions) -> RenderOutput {
+        let render = Render {
+            document: self,
+            options,
+            output: RefCell::new(RenderOutput::default()),
+        };
+        let html = render.to_string();
+
+        let mut output = render.output.into_inner();
+        output.html = html;
+        output
+    }
+
+    /// Headings as `options` renders them, their levels shifted by `heading_offset`.
+    pub fn headings_with(&self, options: &HtmlOptions) -> impl Iterator<Item = Heading> {
+        let mut headings = Vec::new();
+        collect_headings(&self.nodes, options, &mut headings);
+        headings.into_iter()
+    }
+
+    /// The headers, each nested under the previous shallower one even if levels are skipped.
+    pub fn table_of_contents(&self) -> Vec<TocEntry> {
+        let mut toc = Vec::new();
+        for heading in self.headings_with(&HtmlOptions::default()) {
+            push_toc_entry(
+                &mut toc,
+                TocEntry {
+                    level: heading.level,
+                    title: heading.text,
+                    slug: heading.slug,
+                    children: Vec::new(),
+                },
+            );
+        }
+        toc
+    }
+}
+
+fn collect_headings(nodes: &[Node], options: &HtmlOptions, headings: &mut Vec<Heading>) {
+    for node in nodes {
+        match node {
+            Node::Header(level, text) => {
+                let slug = unique_slug(text.slug(), |slug| {
+                    headings.iter().any(|heading| heading.slug == slug)
+                });
+                headings.push(Heading {
+                    level: options.heading_level(*level),
+                    text: text.to_plain_text(),
+                    slug,
+                })
+            }
+            Node::Container { children, .. } => collect_headings(children, options, headings),
+            _ => (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    pub slug: String,
+}
+
+#[must_use]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RenderOutput {
+    pub html: String,
+    pub title: Option<String>, // text of the first `<h1>`
+    pub toc: Vec<TocEntry>,    // nested as `Document::table_of_contents` does
+    pub word_count: usize,
+    pub links: Vec<String>,
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    pub level: usize,
+    pub title: String,
+    pub slug: String,
+    pub children: Vec<TocEntry>,
+}
+
+fn push_toc_entry(toc: &mut Vec<TocEntry>, entry: TocEntry) {
+    match toc.last_mut() {
+        Some(parent) if parent.level < entry.level => push_toc_entry(&mut parent.children, entry),
+        _ => toc.push(entry),
+    }
+}
+
+fn toc_has_slug(toc: &[TocEntry], slug: &str) -> bool {
+    toc.iter()
+        .any(|entry| entry.slug == slug || toc_has_slug(&entry.children, slug))
+}
+
+impl RenderOutput {
+    pub(super) fn collect_node(&mut self, node: &Node, options: &HtmlOptions) {
+        match node {
+            Node::Header(level, text) => {
+                let title = text.to_plain_text();
+                if *level == 1 && self.title.is_none() {
+                    self.title = Some(title.clone());
+                }
+                let slug = unique_slug(text.slug(), |slug| toc_has_slug(&self.toc, slug));
+                push_toc_entry(
+                    &mut self.toc,
+                    TocEntry {
+                        level: options.heading_level(*level),
+                        title,
+                        slug,
+                        children: Vec::new(),
+                    },
+                );
+                self.collect_text(text);
+            }
+            Node::Paragraphe(text) | Node::Quote(_, text) | Node::Footnote(_, text) => {
+                self.collect_text(text)
+            }
+            Node::List(_, paragraphs, _) => {
+                for text in paragraphs {
+                    self.collect_text(text)
+                }
+            }
+            Node::Table { header, rows, .. } => {
+                for cell in header.iter().chain(rows.iter().flatten()) {
+                    self.collect_text(cell)
+                }
+            }
+            // Containers are collected node by node as they get rendered
+            _ => (),
+        }
+    }
+
+    fn collect_text(&mut self, text: &Text) {
+        self.word_count += text.to_plain_text().split_whitespace().count();
+        self.collect_urls(text);
+    }
+
+    fn collect_urls(&mut self, text: &Text) {
+        for frag in text.content.iter() {
+            match frag {
+                TextFragment::Link(_, link, _) => self.links.push(link.to_string()),
+                TextFragment::Image(_, src, _, _) => self.images.push(src.to_string()),
+                TextFragment::Group(_, text) => self.collect_urls(text),
+                TextFragment::Stylised(_, _)
+                | TextFragment::FootnoteRef(_, _)
+                | TextFragment::HardBreak => (),
+            }
+        }
+    }
+}
+
+struct Render<'r, 'a> {
+    document: &'r Document<'a>,
+    options: &'r HtmlOptions,
+    output: RefCell<RenderOutput>,
+}
+
+impl<'r, 'a> fmt::Display for Render<'r, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.document.render(&mut HtmlRenderer {
+            options: self.options,
+            output: Some(&mut self.output.borrow_mut()),
+            f,
+            slugs: BTreeSet::new(),
+            after_nested_list: false,
+        })
+    }
+}
@@ -0,0 +1,670 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use super::{
+    estimated_len, ContainerTag, Heading, HtmlOptions, LineBreakPolicy, LineEnding, TocEntry,
+    VideoEmbed, HTML,
+};
+use crate::{
+    document::{CodeBlock, Document, Node, Span},
+    md::MarkDown,
+};
+
+fn html(s: &str) -> String {
+    HTML::from(MarkDown::from(s)).to_string()
+}
+
+fn html_with(s: &str, options: HtmlOptions) -> String {
+    HTML::from(MarkDown::from(s))
+        .with_options(options)
+        .to_string()
+}
+
+#[test]
+fn containers() {
+    assert_eq!(
+        html(":::note id=n data-x=\"a b\"\nhi\n:::"),
+        "<div class=\"note\" id=\"n\" data-x=\"a b\"><p>hi</p></div>"
+    );
+    let options = HtmlOptions::default().container(
+        "warning",
+        ContainerTag::new("aside").attribute("role", "note"),
+    );
+    assert_eq!(
+        html_with(":::warning\nhi\n:::", options),
+        "<aside role=\"note\"><p>hi</p></aside>"
+    );
+}
+
+#[test]
+fn nested_containers() {
+    assert_eq!(
+        html("::::outer\na\n:::inner\nb\n:::\nc\n::::"),
+        "<div class=\"outer\"><p>a</p><div class=\"inner\"><p>b</p></div><p>c</p></div>"
+    );
+}
+
+#[test]
+fn container_names_and_attributes_are_escaped() {
+    assert_eq!(
+        html("::: note\"><script>x</script> onclick=alert(1)\nhi\n:::"),
+        "<div class=\"note&quot;&gt;&lt;script&gt;x&lt;/script&gt;\" onclick=\"alert(1)\"><p>hi</p></div>"
+    );
+    assert_eq!(
+        html(":::note title='a\"><b>' x\"y=1 a<b\nhi\n:::"),
+        "<div class=\"note\" title=\"a&quot;&gt;&lt;b&gt;\"><p>hi</p></div>"
+    );
+    let safe = HtmlOptions {
+        safe: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with(":::note onclick=alert(1) ONLOAD=x id=a\nhi\n:::", safe),
+        "<div class=\"note\" id=\"a\"><p>hi</p></div>"
+    );
+}
+
+#[test]
+fn video_embeds() {
+    let options = || {
+        HtmlOptions::default()
+            .video_embed(VideoEmbed::youtube())
+            .video_embed(VideoEmbed::vimeo())
+    };
+    assert_eq!(
+        html_with("https://www.youtube.com/watch?v=abc-1_2&t=4", options()),
+        "<iframe src=\"https://www.youtube.com/embed/abc-1_2\" allowfullscreen></iframe>"
+    );
+    assert_eq!(
+        html_with("[vid](https://youtu.be/xyz)", options()),
+        "<iframe src=\"https://www.youtube.com/embed/xyz\" allowfullscreen></iframe>"
+    );
+    assert_eq!(
+        html_with("https://vimeo.com/42", options()),
+        "<iframe src=\"https://player.vimeo.com/video/42\" allowfullscreen></iframe>"
+    );
+    assert!(!html_with("https://youtu.be/x\"onload=\"alert(1)", options()).contains("<iframe"));
+    assert!(!html_with("https://youtu.be/x<y", options()).contains("<iframe"));
+}
+
+#[test]
+fn write_html_appends() {
+    let mut out = String::from("<!-- a -->");
+    HTML::from(MarkDown::from("# a")).write_html(&mut out);
+    HTML::from(MarkDown::from("*b*")).write_html(&mut out);
+    assert_eq!(out, "<!-- a --><h1>a</h1><p><em>b</em></p>");
+}
+
+#[test]
+fn heading_slugs() {
+    let ids = || HtmlOptions {
+        heading_ids: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("## **Bold** Title", ids()),
+        "<h2 id=\"bold-title\"><strong>Bold</strong> Title</h2>"
+    );
+    assert_eq!(
+        html_with("# a [link](/u) *b* `c`\n# a link b c\n# A Link B C", ids()),
+        "<h1 id=\"a-link-b-c\">a <a href=\"/u\">link</a> <em>b</em> <code>c</code></h1>\
+         <h1 id=\"a-link-b-c-1\">a link b c</h1><h1 id=\"a-link-b-c-2\">A Link B C</h1>"
+    );
+}
+
+#[test]
+fn line_break_policies() {
+    let source = "a\n\nb\n\n\n# c\n\nd";
+    for (line_breaks, expected) in [
+        (LineBreakPolicy::Drop, "<p>a</p><p>b</p><h1>c</h1><p>d</p>"),
+        (
+            LineBreakPolicy::Br,
+            "<p>a</p><br><p>b</p><br><h1>c</h1><p>d</p>",
+        ),
+        (
+            LineBreakPolicy::ParagraphGap,
+            "<p>a</p><p></p><p>b</p><p></p><h1>c</h1><p>d</p>",
+        ),
+    ] {
+        let options = HtmlOptions {
+            line_breaks,
+            ..HtmlOptions::default()
+        };
+        assert_eq!(html_with(source, options), expected, "{line_breaks:?}");
+    }
+    assert_eq!(html(source), "<p>a</p><br><p>b</p><br><h1>c</h1><p>d</p>");
+}
+
+#[test]
+fn line_endings() {
+    let pretty = |line_ending| HtmlOptions {
+        pretty_indent: Some(2),
+        line_ending,
+        ..HtmlOptions::default()
+    };
+    let source = "# t\n\na\n\n- x\n- y";
+    assert_eq!(
+        html_with(source, pretty(LineEnding::CrLf)),
+        "<h1>t</h1>\r\n<p>a</p>\r\n<br>\r\n<ul>\r\n  <li>x</li>\r\n  <li>y</li>\r\n</ul>\r\n"
+    );
+    assert_eq!(
+        html_with(source, pretty(LineEnding::Lf)),
+        html_with(source, pretty(LineEnding::CrLf)).replace("\r\n", "\n")
+    );
+    let crlf = HtmlOptions {
+        line_ending: LineEnding::CrLf,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(html_with(source, crlf), html(source));
+}
+
+#[test]
+fn code_block_with_bad_span() {
+    let codeblock = |code| {
+        let document = Document {
+            nodes: vec![Node::CodeBlock(CodeBlock::new("abc", "rs", code))],
+            lines: Vec::new(),
+        };
+        HTML(document, HtmlOptions::default()).to_string()
+    };
+    assert_eq!(
+        codeblock(Span::new(2, 10)),
+        "<pre><code class=\"language-rs\"></code></pre>"
+    );
+    assert_eq!(
+        codeblock(Span::new(1, 2)),
+        "<pre><code class=\"language-rs\">bc</code></pre>"
+    );
+}
+
+const MIXED: &str = "# Title *em*\n\nSome **bold** text with [a link](/u \"t\") and `code`.\n\n\
+                     - a\n- ![i](/i.png)\n\n> quote ~~s~~\n\n```rust\nfn main() {}\n```\n\n\
+                     | a | b |\n|:--|--:|\n| 1 | 2 |\n\n:::note\nhi\n:::\n\n---\n";
+
+#[test]
+fn render_to_matches_display() {
+    struct Chunks(String, usize);
+    impl fmt::Write for Chunks {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.push_str(s);
+            self.1 += 1;
+            Ok(())
+        }
+    }
+
+    let source = MIXED.repeat(20);
+    let document = HTML::from(MarkDown::from(source.as_str()));
+    let mut chunks = Chunks(String::new(), 0);
+    document.render_to(&mut chunks).unwrap();
+    assert_eq!(chunks.0, document.to_string());
+    assert!(chunks.1 > 20, "{}", chunks.1);
+}
+
+#[test]
+fn capacity_is_estimated_up_front() {
+    let source = MIXED.repeat(200);
+    let document = HTML::from(MarkDown::from(source.as_str()));
+    let estimate = estimated_len(&document.0.nodes);
+    let out = document.to_string_with_capacity();
+    assert_eq!(out, document.to_string());
+    // The buffer reserved for the estimate was enough, so it never had to grow
+    assert!(out.len() <= estimate, "{} > {estimate}", out.len());
+    assert!(out.capacity() < out.len() * 2, "{}", out.capacity());
+}
+
+#[test]
+fn image_sizes() {
+    let img = |size| format!("<p><img src=\"/i.png\" alt=\"a\"{size}></p>");
+    for (s, expected) in [
+        ("=200x100", img(" width=\"200\" height=\"100\"")),
+        ("=200x", img(" width=\"200\"")),
+        ("=x100", img(" height=\"100\"")),
+        ("=x", img("")),
+        ("=200", img("")),
+        ("=axb", img("")),
+        ("=-1x2", img("")),
+        ("=200x100px", img("")),
+    ] {
+        assert_eq!(html(&format!("![a](/i.png {s})")), expected, "{s:?}");
+    }
+    assert_eq!(
+        html("![a](/i.png =200x100 \"t\")"),
+        "<p><img src=\"/i.png\" alt=\"a\" title=\"t\" width=\"200\" height=\"100\"></p>"
+    );
+    assert_eq!(
+        html("![a](/i.png=200x100)"),
+        "<p><img src=\"/i.png=200x100\" alt=\"a\"></p>"
+    );
+}
+
+#[test]
+fn lazy_images_and_figures() {
+    let source = "![a](/i.png)\n\ntext ![b](/j.png)";
+    let lazy = HtmlOptions {
+        lazy_images: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with(source, lazy),
+        "<p><img src=\"/i.png\" alt=\"a\" loading=\"lazy\"></p><br>\
+         <p>text <img src=\"/j.png\" alt=\"b\" loading=\"lazy\"></p>"
+    );
+    let figures = HtmlOptions {
+        image_figures: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with(source, figures.clone()),
+        "<figure><img src=\"/i.png\" alt=\"a\"><figcaption>a</figcaption></figure><br>\
+         <p>text <img src=\"/j.png\" alt=\"b\"></p>"
+    );
+    assert_eq!(
+        html_with("![a < b](/k.png \"t\")", figures),
+        "<figure><img src=\"/k.png\" alt=\"a &lt; b\" title=\"t\"><figcaption>a &lt; b</figcaption></figure>"
+    );
+    let both = HtmlOptions {
+        lazy_images: true,
+        image_figures: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("![a](/i.png =20x)", both),
+        "<figure><img src=\"/i.png\" alt=\"a\" width=\"20\" loading=\"lazy\"><figcaption>a</figcaption></figure>"
+    );
+    assert_eq!(
+        html(source),
+        "<p><img src=\"/i.png\" alt=\"a\"></p><br><p>text <img src=\"/j.png\" alt=\"b\"></p>"
+    );
+}
+
+#[test]
+fn code_line_numbers() {
+    let numbered = || HtmlOptions {
+        code_line_numbers: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("```rust {start=10}\na\nb\n```", numbered()),
+        "<pre><code class=\"language-rust\"><span class=\"line\" data-line=\"10\">a</span>\n\
+         <span class=\"line\" data-line=\"11\">b</span>\n</code></pre>"
+    );
+    let first_line = |s| {
+        let out = html_with(s, numbered());
+        let (_, rest) = out.split_once("data-line=\"").unwrap();
+        rest[..rest.find('"').unwrap()].to_string()
+    };
+    assert_eq!(first_line("```rust:7\na\n```"), "7");
+    assert_eq!(first_line("```rust\na\n```"), "1");
+    assert_eq!(first_line("```rust {start=x}\na\n```"), "1");
+}
+
+#[test]
+fn task_progress() {
+    let progress = || HtmlOptions {
+        task_progress: true,
+        ..HtmlOptions::default()
+    };
+    let tasks = "- [x] a\n- [ ] b\n- [x] c\n- [ ] d\n- [x] e";
+    assert_eq!(
+        html_with(tasks, progress()),
+        "<div class=\"task-progress\">3/5</div><ul>\
+         <li><input type=\"checkbox\" disabled checked> a</li><li><input type=\"checkbox\" disabled> b</li>\
+         <li><input type=\"checkbox\" disabled checked> c</li><li><input type=\"checkbox\" disabled> d</li>\
+         <li><input type=\"checkbox\" disabled checked> e</li></ul>"
+    );
+    assert!(!html(tasks).contains("task-progress"));
+    assert_eq!(
+        html_with("- a\n- b", progress()),
+        "<ul><li>a</li><li>b</li></ul>"
+    );
+    let lists = html_with("- [x] a\n- [ ] b\n\npara\n\n- [ ] c", progress());
+    assert!(
+        lists.starts_with("<div class=\"task-progress\">1/2</div>"),
+        "{lists}"
+    );
+    assert!(
+        lists.contains("<div class=\"task-progress\">0/1</div>"),
+        "{lists}"
+    );
+}
+
+#[test]
+fn render_with_metadata() {
+    let source = "# My *Title*\nSome words [here](a.html) ok\n## Part two\n- item ![pic](p.png)\n\
+                  :::note\nInner [x](b.html)\n:::\n# Other";
+    let document = MarkDown::from(source).0;
+    let out = document.render_with_metadata(&HtmlOptions::default());
+    assert_eq!(out.html, html(source));
+    assert_eq!(out.title.as_deref(), Some("My Title"));
+    let entry = |level, title: &str, children| TocEntry {
+        level,
+        title: title.into(),
+        slug: title.to_lowercase().replace(' ', "-"),
+        children,
+    };
+    assert_eq!(
+        out.toc,
+        vec![
+            entry(1, "My Title", vec![entry(2, "Part two", Vec::new())]),
+            entry(1, "Other", Vec::new()),
+        ]
+    );
+    assert_eq!(out.word_count, 13);
+    assert_eq!(out.links, ["a.html", "b.html"]);
+    assert_eq!(out.images, ["p.png"]);
+}
+
+#[test]
+fn code_language_label() {
+    let labelled = || HtmlOptions {
+        show_code_language: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("```rust\nfn f() {}\n```", labelled()),
+        "<div class=\"code-lang\">rust</div><pre><code class=\"language-rust\">fn f() {}\n</code></pre>"
+    );
+    assert_eq!(
+        html_with("```\nx\n```", labelled()),
+        "<pre><code>x\n</code></pre>"
+    );
+    assert!(!html("```rust\nx\n```").contains("code-lang"));
+}
+
+#[test]
+fn escaped_braces() {
+    let escaped = |in_code| HtmlOptions {
+        escape_braces: true,
+        escape_braces_in_code: in_code,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("Hi {{user}}", escaped(false)),
+        "<p>Hi &#123;&#123;user&#125;&#125;</p>"
+    );
+    assert_eq!(html("Hi {{user}}"), "<p>Hi {{user}}</p>");
+    let source = "`{x}` {y}\n```\n{z}\n```";
+    assert_eq!(
+        html_with(source, escaped(false)),
+        "<p><code>{x}</code> &#123;y&#125;</p><pre><code>{z}\n</code></pre>"
+    );
+    assert_eq!(
+        html_with(source, escaped(true)),
+        "<p><code>&#123;x&#125;</code> &#123;y&#125;</p><pre><code>&#123;z&#125;\n</code></pre>"
+    );
+}
+
+#[test]
+fn pretty_nested_lists() {
+    let pretty = |indent| HtmlOptions {
+        pretty_indent: Some(indent),
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("# T\n- a\n    - b\n    - c\n- d\n\ntext", pretty(2)),
+        "<h1>T</h1>\n<ul>\n  <li>a\n    <ul>\n      <li>b</li>\n      <li>c</li>\n    </ul>\n  </li>\n  <li>d</li>\n</ul>\n<br>\n<p>text</p>\n"
+    );
+    assert_eq!(
+        html_with(":::note\n- a\n\t- b\n:::", pretty(4)),
+        "<div class=\"note\">\n<ul>\n    <li>a\n        <ul>\n            <li>b</li>\n        </ul>\n    </li>\n</ul>\n</div>\n"
+    );
+    assert_eq!(
+        html("- a\n    - b"),
+        "<ul><li>a<ul><li>b</li></ul></li></ul>"
+    );
+}
+
+#[test]
+fn html_in_code_is_escaped() {
+    assert_eq!(
+        html("```html\n<div class=\"a\">&amp;</div>\n```"),
+        "<pre><code class=\"language-html\">&lt;div class=\"a\"&gt;&amp;amp;&lt;/div&gt;\n</code></pre>"
+    );
+    let numbered = HtmlOptions {
+        code_line_numbers: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("```\n<b>\n```", numbered),
+        "<pre><code><span class=\"line\" data-line=\"1\">&lt;b&gt;</span>\n</code></pre>"
+    );
+}
+
+#[test]
+fn headings_with_offset() {
+    let document = MarkDown::from("# A\n## B\n###### F\n:::note\n##### E\n:::").0;
+    let offset = HtmlOptions {
+        heading_offset: 1,
+        ..HtmlOptions::default()
+    };
+    let heading = |level, text: &str| Heading {
+        level,
+        text: text.into(),
+        slug: text.to_lowercase(),
+    };
+    assert_eq!(
+        document.headings_with(&offset).collect::<Vec<_>>(),
+        [
+            heading(2, "A"),
+            heading(3, "B"),
+            heading(6, "F"),
+            heading(6, "E")
+        ]
+    );
+    let out = HTML(document.clone(), offset.clone()).to_string();
+    assert!(out.starts_with("<h2>A</h2><h3>B</h3><h6>F</h6>"), "{out}");
+    assert_eq!(document.render_with_metadata(&offset).toc[0].level, 2);
+    let default = HtmlOptions::default();
+    assert_eq!(document.headings_with(&default).next().unwrap().level, 1);
+}
+
+#[test]
+fn dangerous_urls() {
+    let safe = || HtmlOptions {
+        safe: true,
+        ..HtmlOptions::default()
+    };
+    for (s, expected) in [
+        ("[x](javascript:alert`1`)", "<a href=\"\">x</a>"),
+        ("[x](VBScript:msgbox)", "<a href=\"\">x</a>"),
+        ("[x](<java\tscript:alert`1`>)", "<a href=\"\">x</a>"),
+        (
+            "![x](data:text/html;base64,PHNjcmlwdD4=)",
+            "<img src=\"\" alt=\"x\">",
+        ),
+    ] {
+        assert_eq!(html_with(s, safe()), format!("<p>{expected}</p>"), "{s:?}");
+    }
+    for url in [
+        "https://a.org",
+        "mailto:a@b.c",
+        "/rel/a:b",
+        "#top",
+        "page.html?q=a:b",
+    ] {
+        let out = html_with(&format!("[x]({url})"), safe());
+        assert!(out.contains(&format!("href=\"{url}\"")), "{out}");
+    }
+    assert_eq!(
+        html("[x](javascript:alert`1`)"),
+        "<p><a href=\"javascript:alert`1`\">x</a></p>"
+    );
+}
+
+#[test]
+fn html_page_in_code() {
+    let page = "<!DOCTYPE html>\n<html lang=\"en\">\n<body>\n  <div class='a'>&amp; &copy; 1 < 2</div>\n  <script>alert(\"x\")</script>\n</body>\n</html>";
+    assert_eq!(
+        html(&format!("```html\n{page}\n```")),
+        "<pre><code class=\"language-html\">&lt;!DOCTYPE html&gt;\n&lt;html lang=\"en\"&gt;\n&lt;body&gt;\n  &lt;div class='a'&gt;&amp;amp; &amp;copy; 1 &lt; 2&lt;/div&gt;\n  &lt;script&gt;alert(\"x\")&lt;/script&gt;\n&lt;/body&gt;\n&lt;/html&gt;\n</code></pre>"
+    );
+    assert_eq!(
+        html("`<b>&amp;\"q\"`"),
+        "<p><code>&lt;b&gt;&amp;amp;\"q\"</code></p>"
+    );
+    assert_eq!(
+        html("    <div>&copy;</div>"),
+        "<pre><code>&lt;div&gt;&amp;copy;&lt;/div&gt;\n</code></pre>"
+    );
+}
+
+#[test]
+fn text_is_escaped() {
+    assert_eq!(
+        html("a <script> & \"b\" `<i>` [x<y](/a\"b) ![<c>](/d\"e)"),
+        "<p>a &lt;script&gt; &amp; &quot;b&quot; <code>&lt;i&gt;</code> \
+         <a href=\"/a&quot;b\">x&lt;y</a> <img src=\"/d&quot;e\" alt=\"&lt;c&gt;\"></p>"
+    );
+    assert_eq!(
+        html("<b>\"q\"</b>"),
+        "<p>&lt;b&gt;&quot;q&quot;&lt;/b&gt;</p>"
+    );
+}
+
+#[test]
+fn heading_ids() {
+    let ids = || HtmlOptions {
+        heading_ids: true,
+        ..HtmlOptions::default()
+    };
+    assert_eq!(
+        html_with("# *Hello*, World!\n# Hello World\n# ?!", ids()),
+        "<h1 id=\"hello-world\"><em>Hello</em>, World!</h1><h1 id=\"hello-world-1\">Hello World</h1><h1>?!</h1>"
+    );
+    assert_eq!(html("# A"), "<h1>A</h1>");
+    let out = MarkDown::from("# A\n# A").0.render_with_metadata(&ids());
+    let slugs: Vec<_> = out.toc.iter().map(|entry| entry.slug.as_str()).collect();
+    assert_eq!(slugs, ["a", "a-1"]);
+}
+
+#[test]
+fn table_of_contents() {
+    let toc = MarkDown::from("## Intro\n# A\n### Deep\n## B\n# A")
+        .0
+        .table_of_contents();
+    let entry = |level, title: &str, slug: &str, children| TocEntry {
+        level,
+        title: title.into(),
+        slug: slug.into(),
+        children,
+    };
+    assert_eq!(
+        toc,
+        [
+            entry(2, "Intro", "intro", Vec::new()),
+            entry(
+                1,
+                "A",
+                "a",
+                vec![
+                    entry(3, "Deep", "deep", Vec::new()),
+                    entry(2, "B", "b", Vec::new())
+                ]
+            ),
+            entry(1, "A", "a-1", Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn code_class() {
+    assert_eq!(html("```\nx\n```"), "<pre><code>x\n</code></pre>");
+    assert_eq!(
+        html("```rust ignore\nx\n```"),
+        "<pre><code class=\"language-rust\">x\n</code></pre>"
+    );
+    assert_eq!(
+        html("```a\"><b\nx\n```"),
+        "<pre><code class=\"language-a&quot;&gt;&lt;b\">x\n</code></pre>"
+    );
+}
+
+#[test]
+fn external_links() {
+    let options = || HtmlOptions {
+        external_links_new_tab: true,
+        link_base: Some("https://example.com".into()),
+        ..HtmlOptions::default()
+    };
+    let new_tab = " target=\"_blank\" rel=\"noopener noreferrer\"";
+    assert_eq!(
+        html_with(
+            "[a](https://other.org/x) [b](https://Example.com/about) [c](/local) [d](#top) www.foo.com",
+            options()
+        ),
+        format!(
+            "<p><a href=\"https://other.org/x\"{new_tab}>a</a> <a href=\"https://Example.com/about\">b</a> \
+             <a href=\"/local\">c</a> <a href=\"#top\">d</a> <a href=\"http://www.foo.com\"{new_tab}>www.foo.com</a></p>"
+        )
+    );
+    assert_eq!(
+        html_with("[t](https://x.org \"T\")", options()),
+        format!("<p><a href=\"https://x.org\"{new_tab} title=\"T\">t</a></p>")
+    );
+    assert!(!html("[a](https://other.org)").contains("_blank"));
+}
+
+#[test]
+fn base_url() {
+    let url = |s: &str, base: &str| {
+        let options = HtmlOptions {
+            base_url: Some(base.into()),
+            ..HtmlOptions::default()
+        };
+        let out = html_with(s, options);
+        let (_, rest) = out.split_once("=\"").unwrap();
+        rest[..rest.find('"').unwrap()].to_string()
+    };
+    for (s, base, expected) in [
+        ("[a](img.png)", "/blog/post/", "/blog/post/img.png"),
+        ("[a](../other/)", "/blog/post/", "/blog/other/"),
+        ("[a](../../../x)", "/blog/post/", "/x"),
+        (
+            "[a](./x/./y?q=1#f)",
+            "/blog/post/index.html",
+            "/blog/post/x/y?q=1#f",
+        ),
+        ("[a](/root.png)", "/blog/post/", "/root.png"),
+        ("[a](x)", "https://example.com", "https://example.com/x"),
+        (
+            "[a](/x)",
+            "https://example.com/blog/",
+            "https://example.com/x",
+        ),
+        ("[a](../../x)", "docs/", "../x"),
+        ("![a](pic.png)", "/blog/", "/blog/pic.png"),
+    ] {
+        assert_eq!(url(s, base), expected, "{s:?} against {base:?}");
+    }
+    for url_kept in ["#top", "https://a.org/x", "mailto:a@b.c", "//cdn.org/x"] {
+        assert_eq!(url(&format!("[a]({url_kept})"), "/blog/"), url_kept);
+    }
+    assert_eq!(html("[a](x)"), "<p><a href=\"x\">a</a></p>");
+}
+
+#[test]
+fn xhtml() {
+    let xhtml = || HtmlOptions {
+        xhtml: true,
+        ..HtmlOptions::default()
+    };
+    let source = "a\\\nb\n\n***\n\n![i](x.png \"t\")";
+    assert_eq!(
+        html_with(source, xhtml()),
+        "<p>a<br/>\nb</p><br/><hr/><br/><p><img src=\"x.png\" alt=\"i\" title=\"t\"/></p>"
+    );
+    assert_eq!(
+        html(source),
+        "<p>a<br>\nb</p><br><hr><br><p><img src=\"x.png\" alt=\"i\" title=\"t\"></p>"
+    );
+    assert_eq!(
+        html_with("- [x] a\n- [ ] b", xhtml()),
+        "<ul><li><input type=\"checkbox\" disabled=\"disabled\" checked=\"checked\"/> a</li>\
+         <li><input type=\"checkbox\" disabled=\"disabled\"/> b</li></ul>"
+    );
+}
@@ -1,9 +1,25 @@
-#![feature(let_chains)]
-#![feature(char_indices_offset)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 mod document;
 mod html;
 mod md;
+mod plain;
+mod render;
+mod visit;
 
-pub use html::HTML;
-pub use md::MarkDown;
+pub use document::{
+    Alignment, CodeBlock, Document, DocumentOwned, DocumentStats, ImageSize, Inline, ListKind,
+    Node, Span, Style, Text, TextFragment,
+};
+pub use html::{
+    to_html, to_html_with, ContainerTag, Heading, HtmlOptions, HtmlRenderer, LineBreakPolicy,
+    LineEnding, RenderOutput, TocEntry, VideoEmbed, HTML,
+};
+#[cfg(feature = "normalization")]
+pub use md::Normalization;
+pub use md::{MarkDown, MdError, ParserOptions, Token, Tokens};
+pub use plain::{PlainText, PlainTextOptions};
+pub use render::Renderer;
+pub use visit::Visitor;
@@ -0,0 +1,215 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{cell::RefCell, cmp::Ordering, ops::Deref};
+
+use super::{is_code_block_annonce, is_code_block_close, options::ParserOptions, split_lines};
+
+// The options along what is gathered over the whole document before parsing it
+pub(super) struct Context<'a, 'o> {
+    options: &'o ParserOptions,
+    link_definitions: BTreeMap<Label<'a>, (&'a str, Option<&'a str>)>,
+    footnote_definitions: BTreeMap<Label<'a>, (usize, &'a str)>, // line from 1, text
+    // Labels of the footnotes referred to, numbered from 1 in the order they come
+    footnotes: RefCell<Vec<Label<'a>>>,
+    // The first label referred to without being defined, as strict parsing reports it
+    undefined_reference: RefCell<Option<String>>,
+}
+
+impl<'a, 'o> Context<'a, 'o> {
+    pub(super) fn new(s: &'a str, options: &'o ParserOptions) -> Self {
+        let (link_definitions, footnote_definitions) = collect_definitions(s);
+        Self {
+            options,
+            link_definitions,
+            footnote_definitions,
+            footnotes: RefCell::new(Vec::new()),
+            undefined_reference: RefCell::new(None),
+        }
+    }
+
+    pub(super) fn link_definition(&self, label: &str) -> Option<(&'a str, Option<&'a str>)> {
+        if !is_label_short_enough(label) {
+            return None;
+        }
+        self.link_definitions.get(&Label(label)).copied()
+    }
+
+    pub(super) fn refer_to_undefined(&self, label: &str) {
+        self.undefined_reference
+            .borrow_mut()
+            .get_or_insert_with(|| label.into());
+    }
+
+    pub(super) fn undefined_reference(&self) -> Option<String> {
+        self.undefined_reference.borrow_mut().take()
+    }
+
+    // The number of a defined footnote, given on its first reference
+    pub(super) fn footnote_number(&self, label: &'a str) -> Option<usize> {
+        let label = Label(label);
+        if !is_label_short_enough(label.0) || !self.footnote_definitions.contains_key(&label) {
+            return None;
+        }
+
+        let mut footnotes = self.footnotes.borrow_mut();
+        let idx = match footnotes.iter().position(|footnote| *footnote == label) {
+            Some(idx) => idx,
+            None => {
+                footnotes.push(label);
+                footnotes.len() - 1
+            }
+        };
+        Some(idx + 1)
+    }
+
+    pub(super) fn footnote_definition(&self, number: usize) -> Option<(usize, &'a str)> {
+        let footnotes = self.footnotes.borrow();
+        let label = footnotes.get(number.checked_sub(1)?)?;
+        self.footnote_definitions.get(label).copied()
+    }
+}
+
+impl<'a, 'o> Deref for Context<'a, 'o> {
+    type Target = ParserOptions;
+
+    fn deref(&self) -> &ParserOptions {
+        self.options
+    }
+}
+
+type Definitions<'a> = (
+    BTreeMap<Label<'a>, (&'a str, Option<&'a str>)>,
+    BTreeMap<Label<'a>, (usize, &'a str)>,
+);
+
+// Definitions may sit in quotes and list items too, but not in code blocks
+fn collect_definitions(s: &str) -> Definitions<'_> {
+    let mut links = BTreeMap::new();
+    let mut footnotes = BTreeMap::new();
+    let mut fence = None;
+
+    for (idx, (_, line)) in split_lines(s).into_iter().enumerate() {
+        if let Some((line_fence, len, _)) = is_code_block_annonce(line) {
+            fence = match fence {
+                None => Some((line_fence, len)),
+                Some(fence) if is_code_block_close(line, fence) => None,
+                fence => fence,
+            };
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+
+        // The first definition of a label wins
+        let line = strip_block_prefixes(line);
+        if let Some((label, url, title)) = try_parse_link_definition(line) {
+            links.entry(Label(label)).or_insert((url, title));
+        } else if let Some((label, text)) = try_parse_footnote_definition(line) {
+            footnotes.entry(Label(label)).or_insert((idx + 1, text));
+        }
+    }
+
+    (links, footnotes)
+}
+
+// `[label]: url "title"`, the url possibly between `<>` and the title being left out
+pub(super) fn try_parse_link_definition(line: &str) -> Option<(&str, &str, Option<&str>)> {
+    let (label, rest) = line.trim().strip_prefix('[')?.split_once("]:")?;
+    if label.trim().is_empty()
+        || label.contains(['[', ']'])
+        || label.starts_with('^')
+        || !is_label_short_enough(label)
+    {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let (url, title) = match rest.strip_prefix('<') {
+        Some(rest) => rest.split_once('>')?,
+        None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+    };
+
+    if url.is_empty() {
+        return None;
+    }
+    let title = title.trim();
+    if title.is_empty() {
+        return Some((label, url, None));
+    }
+
+    let title = [('"', '"'), ('\'', '\''), ('(', ')')]
+        .into_iter()
+        .find_map(|(open, close)| title.strip_prefix(open)?.strip_suffix(close))?;
+    Some((label, url, Some(title)))
+}
+
+// `[^label]: text`
+pub(super) fn try_parse_footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let (label, text) = line.trim().strip_prefix("[^")?.split_once("]:")?;
+    if label.is_empty() || label.contains(|c: char| c.is_whitespace() || c == '[' || c == ']') {
+        return None;
+    }
+
+    Some((label, text.trim()))
+}
+
+pub(super) fn strip_block_prefixes(mut line: &str) -> &str {
+    loop {
+        let trimmed = line.trim_start();
+        let numbered = trimmed.trim_start_matches(char::is_numeric);
+
+        let rest = trimmed
+            .strip_prefix('>')
+            .or(trimmed.strip_prefix("- "))
+            .or(trimmed.strip_prefix("* "))
+            .or(trimmed.strip_prefix("+ "))
+            .or(numbered
+                .strip_prefix(". ")
+                .or(numbered.strip_prefix(") "))
+                .filter(|_| numbered.len() < trimmed.len()));
+        match rest {
+            Some(rest) => line = rest,
+            None => return trimmed,
+        }
+    }
+}
+
+// Labels match whatever their case and whitespace, compared as they go rather than normalized into
+// a new string on every lookup
+#[derive(Clone, Copy)]
+pub(super) struct Label<'a>(&'a str);
+
+impl Label<'_> {
+    fn normalized(&self) -> impl Iterator<Item = char> + '_ {
+        self.0
+            .split_whitespace()
+            .enumerate()
+            .flat_map(|(idx, word)| (idx > 0).then_some(' ').into_iter().chain(word.chars()))
+            .flat_map(char::to_lowercase)
+    }
+}
+
+impl PartialEq for Label<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Label<'_> {}
+
+impl PartialOrd for Label<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Label<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.normalized().cmp(other.normalized())
+    }
+}
+
+// As CommonMark does, a label runs over 999 characters at most
+fn is_label_short_enough(label: &str) -> bool {
+    label.chars().nth(999).is_none()
+}
@@ -0,0 +1,87 @@
+// The named entities most often met in text, far from the full HTML list
+const NAMED_ENTITIES: [(&str, char); 48] = [
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("shy", '\u{ad}'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("deg", '°'),
+    ("plusmn", '±'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("micro", 'µ'),
+    ("para", '¶'),
+    ("sect", '§'),
+    ("middot", '·'),
+    ("bull", '•'),
+    ("hellip", '…'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("euro", '€'),
+    ("pound", '£'),
+    ("yen", '¥'),
+    ("cent", '¢'),
+    ("larr", '←'),
+    ("rarr", '→'),
+    ("uarr", '↑'),
+    ("darr", '↓'),
+    ("harr", '↔'),
+    ("ne", '≠'),
+    ("le", '≤'),
+    ("ge", '≥'),
+    ("infin", '∞'),
+    ("frac12", '½'),
+    ("frac14", '¼'),
+    ("frac34", '¾'),
+    ("iexcl", '¡'),
+    ("iquest", '¿'),
+    ("dagger", '†'),
+    ("check", '✓'),
+];
+
+// The longest HTML entity name, `&CounterClockwiseContourIntegral;`, is 33 bytes
+const MAX_REFERENCE_LEN: usize = 33;
+
+/// The character of the entity or numeric character reference starting `s`, along its length.
+pub fn entity_at(s: &str) -> Option<(char, usize)> {
+    // The `;` is only looked for as far as the longest reference could run, so that scanning a
+    // line full of `&` stays linear
+    let end = s.bytes().take(MAX_REFERENCE_LEN).position(|b| b == b';')?;
+    let name = s.strip_prefix('&')?.get(..end - 1)?;
+
+    let c = if let Some(number) = name.strip_prefix('#') {
+        match number.strip_prefix(['x', 'X']) {
+            Some(hex) => numeric_reference(hex, 16, 6)?,
+            None => numeric_reference(number, 10, 7)?,
+        }
+    } else {
+        NAMED_ENTITIES
+            .iter()
+            .find_map(|(entity, c)| (*entity == name).then_some(*c))?
+    };
+    Some((c, end + 1))
+}
+
+// Out of range code points are replaced rather than left literal, as CommonMark does
+fn numeric_reference(digits: &str, radix: u32, max_len: usize) -> Option<char> {
+    if digits.is_empty() || digits.len() > max_len || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let code = u32::from_str_radix(digits, radix).ok()?;
+    Some(
+        char::from_u32(code)
+            .filter(|c| *c != '\0')
+            .unwrap_or(char::REPLACEMENT_CHARACTER),
+    )
+}
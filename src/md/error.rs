@@ -0,0 +1,88 @@
+use alloc::string::String;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MdError {
+    InputTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    // Line of the opening fence, from 1
+    UnterminatedCodeBlock {
+        line: usize,
+    },
+    UnevenTableRow {
+        line: usize,
+        cells: usize,
+        columns: usize,
+    },
+    // A `[text][label]` link or image whose label is never defined
+    InvalidReference {
+        line: usize,
+        label: String,
+    },
+    #[cfg(feature = "std")]
+    Io {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+}
+
+impl fmt::Display for MdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InputTooLarge { size, limit } => f.write_fmt(format_args!(
+                "input of {size} bytes exceeds the limit of {limit} bytes"
+            )),
+            Self::UnterminatedCodeBlock { line } => f.write_fmt(format_args!(
+                "code block opened at line {line} is never closed"
+            )),
+            Self::UnevenTableRow {
+                line,
+                cells,
+                columns,
+            } => f.write_fmt(format_args!(
+                "table row at line {line} has {cells} cells for {columns} columns"
+            )),
+            Self::InvalidReference { line, label } => f.write_fmt(format_args!(
+                "reference to the undefined label `{label}` at line {line}"
+            )),
+            #[cfg(feature = "std")]
+            Self::Io { message, .. } => {
+                f.write_fmt(format_args!("failed to read the input: {message}"))
+            }
+        }
+    }
+}
+
+impl MdError {
+    // Lines of a container's inner text count from the line after its annonce
+    pub(super) fn shift_lines(self, by: usize) -> Self {
+        match self {
+            Self::UnterminatedCodeBlock { line } => Self::UnterminatedCodeBlock { line: line + by },
+            Self::UnevenTableRow {
+                line,
+                cells,
+                columns,
+            } => Self::UnevenTableRow {
+                line: line + by,
+                cells,
+                columns,
+            },
+            err => err,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MdError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for MdError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io {
+            kind: error.kind(),
+            message: alloc::string::ToString::to_string(&error),
+        }
+    }
+}
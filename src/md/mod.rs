@@ -1,53 +1,710 @@
+mod context;
+mod entity;
+mod error;
+#[cfg(feature = "normalization")]
+mod normalization;
+mod options;
 mod queue;
+mod serialize;
+#[cfg(test)]
+mod tests;
+mod tokenizer;
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
 
 use crate::document::{
-    compacte_nodes, CodeBlock, Document, ListKind, Node, Span, Style, Text, TextFragment,
+    compacte_lined_nodes, Alignment, CodeBlock, Document, Edit, ImageSize, ListKind, Node, Span,
+    Style, Text, TextFragment,
 };
 
+use core::{ops::Range, str::FromStr};
+
 use queue::Queue;
 
+pub use error::MdError;
+#[cfg(feature = "normalization")]
+pub use options::Normalization;
+pub use options::ParserOptions;
+pub use tokenizer::{Token, Tokens};
+
+use self::context::{
+    strip_block_prefixes, try_parse_footnote_definition, try_parse_link_definition, Context,
+};
+use self::entity::entity_at;
 use self::queue::pop_min2;
 
 const RULE_CHARS: [char; 3] = ['*', '-', '_'];
 
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// htmd::MarkDown::from("# Parsed for nothing");
+/// ```
+#[must_use]
 pub struct MarkDown<'a>(pub Document<'a>);
 
+impl<'a> MarkDown<'a> {
+    pub fn parse_with(s: &'a str, options: &ParserOptions) -> Self {
+        let lenient = ParserOptions {
+            strict: false,
+            ..options.clone()
+        };
+        Self::try_parse_with(s, &lenient).expect("lenient parsing never fails")
+    }
+
+    /// Parses strictly without copying `s`, as `TryFrom<&str>` would if `From<&str>` didn't
+    /// already give it.
+    ///
+    /// ```
+    /// let source = String::from("| a |\n|---|\n| 1 | 2 |");
+    /// let err = htmd::MarkDown::try_parse(&source).err().unwrap();
+    /// assert_eq!(err.to_string(), "table row at line 3 has 2 cells for 1 columns");
+    /// ```
+    pub fn try_parse(s: &'a str) -> Result<Self, MdError> {
+        let strict = ParserOptions {
+            strict: true,
+            ..ParserOptions::default()
+        };
+        Self::try_parse_with(s, &strict)
+    }
+
+    pub fn try_parse_with(s: &'a str, options: &ParserOptions) -> Result<Self, MdError> {
+        // Some editors save a byte-order mark which isn't part of the text
+        let s = s.strip_prefix('\u{FEFF}').unwrap_or(s);
+        // Nothing to parse makes an empty document rather than a blank line
+        if s.is_empty() {
+            return Ok(MarkDown(Document {
+                nodes: Vec::new(),
+                lines: Vec::new(),
+            }));
+        }
+        let context = Context::new(s, options);
+        let (mut nodes, mut lines) = parse_blocks(s, &context)?;
+        if let Some(label) = context.undefined_reference().filter(|_| options.strict) {
+            let reference = format!("][{label}]");
+            let line = split_lines(s)
+                .iter()
+                .position(|(_, line)| line.contains(&reference))
+                .map_or(0, |idx| idx + 1);
+            return Err(MdError::InvalidReference { line, label });
+        }
+        let (footnotes, footnote_lines) = parse_footnotes(&context);
+        nodes.extend(footnotes);
+        lines.extend(footnote_lines);
+
+        #[cfg(feature = "normalization")]
+        let nodes = normalization::normalize(nodes, options.normalization);
+
+        Ok(MarkDown(Document { nodes, lines }))
+    }
+
+    /// Refuses inputs over `max_bytes` instead of parsing them, to bound the work done on
+    /// untrusted markdown.
+    pub fn parse_limited(s: &'a str, max_bytes: usize) -> Result<Self, MdError> {
+        if s.len() > max_bytes {
+            return Err(MdError::InputTooLarge {
+                size: s.len(),
+                limit: max_bytes,
+            });
+        }
+
+        Ok(Self::from(s))
+    }
+}
+
+#[cfg(feature = "std")]
+impl MarkDown<'static> {
+    /// Parses the lines `reader` yields into an owned document.
+    ///
+    /// Lines are read one at a time, but this isn't a streaming parser: link definitions and
+    /// footnotes may come after what refers to them, so the lines are gathered before being
+    /// parsed, then dropped.
+    pub fn from_reader<R: std::io::BufRead>(reader: R) -> Result<Self, MdError> {
+        Self::from_reader_with(reader, &ParserOptions::default())
+    }
+
+    /// `from_reader` with `options`, gathering the lines as well.
+    pub fn from_reader_with<R: std::io::BufRead>(
+        mut reader: R,
+        options: &ParserOptions,
+    ) -> Result<Self, MdError> {
+        let mut s = String::new();
+        // Line endings are kept, `\r\n` ones included, for the lines to split as they would
+        while reader.read_line(&mut s)? > 0 {}
+        let markdown = MarkDown::try_parse_with(&s, options)?;
+        Ok(MarkDown(markdown.0.into_owned()))
+    }
+}
+
 impl<'a> From<&'a str> for MarkDown<'a> {
     fn from(s: &'a str) -> Self {
-        let mut nodes = Vec::new();
-
-        let mut codeblock = None;
-        let mut pre_offset = 0;
-        let mut chars_indices = s.char_indices();
-        while let Some((_, c)) = chars_indices.next() {
-            if c == '\n' {
-                let line = &s[pre_offset..chars_indices.offset() - 1];
-                if let Some(node) = parse_line(s, pre_offset, line, &mut codeblock) {
-                    nodes.push(node);
+        Self::parse_with(s, &ParserOptions::default())
+    }
+}
+
+/// Parses into an owned document with the default options, making the best of malformed input
+/// as `MarkDown::from` does. `MarkDown::try_parse` reports it as an error instead.
+///
+/// ```
+/// let markdown = "```rust\nfn main() {}".parse::<htmd::MarkDown>();
+/// assert!(markdown.is_ok());
+/// ```
+impl FromStr for MarkDown<'static> {
+    type Err = MdError;
+
+    fn from_str(s: &str) -> Result<Self, MdError> {
+        let markdown = MarkDown::try_parse_with(s, &ParserOptions::default())?;
+        Ok(MarkDown(markdown.0.into_owned()))
+    }
+}
+
+// ============================================== BLOCKS ==============================================
+
+// The nodes along the lines each of them comes from, as `Document::lines` holds them
+fn parse_blocks<'a>(s: &'a str, options: &Context<'a, '_>) -> Result<Blocks<'a>, MdError> {
+    let lines = split_lines(s);
+
+    let mut nodes = Vec::new();
+    // From 0, the end excluded, as the indexes in `lines`
+    let mut node_lines: Vec<Range<usize>> = Vec::new();
+    let mut container_lines = Vec::new();
+    let mut codeblock = None;
+    let mut code_start = 0;
+    // Where the text of the last list item starts, as `3` for `1. item`
+    let mut item_indent = 0;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let (offset, line) = lines[idx];
+
+        if let Some(directive) = is_htmd_comment(line).filter(|_| codeblock.is_none()) {
+            // An unbalanced `ignore-start` hides everything up to the end of the document
+            if directive == "ignore-start" {
+                idx = lines[idx..]
+                    .iter()
+                    .position(|(_, line)| is_htmd_comment(line) == Some("ignore-end"))
+                    .map_or(lines.len(), |end| idx + end);
+            }
+            idx += 1;
+            continue;
+        }
+
+        if let Some((name, attrs)) = is_container_annonce(line).filter(|_| codeblock.is_none()) {
+            let end = find_container_end(&lines, idx);
+            let inner_start = lines.get(idx + 1).map_or(s.len(), |(offset, _)| *offset);
+            let inner_end = lines.get(end).map_or(s.len(), |_| {
+                let (offset, line) = lines[end - 1];
+                offset + line.len()
+            });
+            // A blank line alone inside is a line break like anywhere else
+            let (children, children_lines) = if end > idx + 1 {
+                parse_blocks(&s[inner_start..inner_end], options)
+                    .map_err(|err| err.shift_lines(idx + 1))?
+            } else {
+                (Vec::new(), Vec::new())
+            };
+            container_lines.push(
+                children_lines
+                    .into_iter()
+                    .map(|child_lines| child_lines.start + idx + 1..child_lines.end + idx + 1)
+                    .collect::<Vec<_>>(),
+            );
+
+            nodes.push(Node::Container {
+                name: name.into(),
+                attrs: attrs
+                    .into_iter()
+                    .map(|(attr, value)| (attr.into(), value.into()))
+                    .collect(),
+                children,
+            });
+            node_lines.push(idx..(end + 1).min(lines.len()));
+            idx = end + 1;
+            continue;
+        }
+
+        // Past blank lines, a paragraph indented up to the text of the list item above belongs to it
+        let after_item = matches!(nodes.last(), Some(Node::LineBreak))
+            && nodes
+                .iter()
+                .rfind(|node| !matches!(node, Node::LineBreak))
+                .is_some_and(|node| matches!(node, Node::List(_, _, _)));
+        if after_item && codeblock.is_none() && indent_width(line, options.tab_width) >= item_indent
+        {
+            // A definition in the item is left out of it, as anywhere else
+            if is_definition(line) {
+                idx += 1;
+                continue;
+            }
+            if let Some(Node::Paragraphe(text)) =
+                parse_line(s, offset, line.trim_start(), &mut None, options)
+            {
+                while nodes
+                    .pop_if(|node| matches!(node, Node::LineBreak))
+                    .is_some()
+                {
+                    node_lines.pop();
+                }
+                if let Some(Node::List(_, paragraphs, _)) = nodes.last_mut() {
+                    paragraphs.push(text);
                 }
-                pre_offset = chars_indices.offset();
+                idx += 1;
+                if let Some(item_lines) = node_lines.last_mut() {
+                    item_lines.end = idx;
+                }
+                continue;
+            }
+        }
+
+        // Indented code can't interrupt a paragraph, nor a list where the indentation nests items
+        if codeblock.is_none()
+            && !line.trim().is_empty()
+            && !matches!(
+                nodes.last(),
+                Some(Node::Paragraphe(_) | Node::List(_, _, _))
+            )
+            && strip_code_indent(line, options.tab_width).is_some()
+            && !is_list_item(line)
+        {
+            let mut end = idx;
+            while lines.get(end).is_some_and(|(_, line)| {
+                line.trim().is_empty() || strip_code_indent(line, options.tab_width).is_some()
+            }) {
+                end += 1;
+            }
+            // Blank lines only belong to the code when it goes on after them
+            while end > idx + 1 && lines[end - 1].1.trim().is_empty() {
+                end -= 1;
+            }
+
+            let code: String = lines[idx..end]
+                .iter()
+                .map(|(_, line)| {
+                    strip_code_indent(line, options.tab_width)
+                        .unwrap_or_default()
+                        .to_owned()
+                        + "\n"
+                })
+                .collect();
+            nodes.push(Node::CodeBlock(CodeBlock::from_code(code, "")));
+            node_lines.push(idx..end);
+            idx = end;
+            continue;
+        }
+
+        let table_delimiter = lines
+            .get(idx + 1)
+            .filter(|_| options.tables && codeblock.is_none() && line.contains('|'))
+            .and_then(|(_, delimiter)| parse_table_delimiter(delimiter));
+        if let Some(alignments) = table_delimiter {
+            let header = split_table_row(line, options);
+            if header.len() == alignments.len() {
+                let start = idx;
+                let mut rows = Vec::new();
+                idx += 2;
+                while let Some((_, line)) = lines.get(idx).filter(|(_, line)| {
+                    !line.trim().is_empty() && line.contains('|') && !is_block_start(line, options)
+                }) {
+                    let mut row = split_table_row(line, options);
+                    if options.strict && row.len() != header.len() {
+                        return Err(MdError::UnevenTableRow {
+                            line: idx + 1,
+                            cells: row.len(),
+                            columns: header.len(),
+                        });
+                    }
+                    row.resize_with(header.len(), || parse_text("", options));
+                    rows.push(row);
+                    idx += 1;
+                }
+
+                nodes.push(Node::Table {
+                    header,
+                    alignments,
+                    rows,
+                });
+                node_lines.push(start..idx);
+                continue;
             }
         }
-        let line = &s[pre_offset..chars_indices.offset()];
-        if let Some(node) = parse_line(s, pre_offset, line, &mut codeblock) {
+
+        // Definitions only give their url to the links referring to them, and footnotes end
+        // the document
+        if codeblock.is_none() && is_definition(line) {
+            idx += 1;
+            continue;
+        }
+
+        let in_code = codeblock.is_some();
+        let node = parse_line(s, offset, line, &mut codeblock, options);
+        if !in_code && codeblock.is_some() {
+            code_start = idx;
+        }
+        let mut start = if in_code { code_start } else { idx };
+        idx += 1;
+
+        // A paragraph line right below a list item goes on with its text, though an empty item
+        // only takes the lines indented up to where its text would be
+        if let (Some(Node::Paragraphe(_)), Some(Node::List(_, paragraphs, _))) =
+            (&node, nodes.last_mut())
+        {
+            if paragraphs.iter().all(|text| text.content.is_empty())
+                && indent_width(line, options.tab_width) < item_indent
+            {
+                nodes.push(node.unwrap());
+                node_lines.push(start..idx);
+                continue;
+            }
+            if let Some(text) = paragraphs.last_mut() {
+                text.join(parse_paragraphe_line(line.trim_start(), options), "\n");
+            }
+            if let Some(item_lines) = node_lines.last_mut() {
+                item_lines.end = idx;
+            }
+            continue;
+        }
+
+        if let Some(node) = node {
+            if matches!(node, Node::List(_, _, _)) {
+                item_indent = list_item_indent(line, options.tab_width);
+            }
+            let setext_level = lines
+                .get(idx)
+                .and_then(|(_, line)| is_setext_underline(line));
+            let mut node = match (setext_level, node) {
+                (Some(level), Node::Paragraphe(mut text)) => {
+                    // The underline turns the whole paragraph above it into the header
+                    while let Some(Node::Paragraphe(mut pre_text)) =
+                        nodes.pop_if(|node| matches!(node, Node::Paragraphe(_)))
+                    {
+                        pre_text.join(text, "\n");
+                        text = pre_text;
+                        start = node_lines.pop().map_or(start, |pre_lines| pre_lines.start);
+                    }
+                    text.trim_hard_break();
+                    idx += 1;
+                    Node::Header(level, text)
+                }
+                (_, node) => node,
+            };
+
+            if options.join_wrapped_headers {
+                if let Node::Header(_, text) = &mut node {
+                    while let Some((_, next_line)) = lines
+                        .get(idx)
+                        .filter(|(_, next_line)| is_header_continuation(next_line))
+                    {
+                        text.join(parse_text(next_line.trim(), options), " ");
+                        idx += 1;
+                    }
+                }
+            }
             nodes.push(node);
+            node_lines.push(start..idx);
+        }
+    }
+
+    // The input ended inside a code block
+    if let Some((_, mut codeblock)) = codeblock {
+        if options.strict {
+            let line = lines
+                .iter()
+                .position(|(offset, _)| *offset >= codeblock.code.offset)
+                .unwrap_or(lines.len());
+            return Err(MdError::UnterminatedCodeBlock { line });
+        }
+
+        // Without a closing fence, the last line has no newline to count, though it still ends
+        // with one as every line of code does
+        codeblock.code.offset = codeblock.code.offset.min(s.len());
+        codeblock.code.length = codeblock.code.length.min(s.len() - codeblock.code.offset);
+        nodes.push(Node::CodeBlock(
+            codeblock.normalize_line_endings().end_last_line(),
+        ));
+        node_lines.push(code_start..lines.len());
+    }
+
+    // Lines count from 1 once parsed, and containers' children follow them
+    let mut container_lines = container_lines.into_iter();
+    let mut blocks_lines = Vec::with_capacity(node_lines.len());
+    let nodes = compacte_lined_nodes(nodes.into_iter().zip(node_lines).collect())
+        .into_iter()
+        .map(|(node, lines)| {
+            blocks_lines.push(lines.start + 1..lines.end + 1);
+            if matches!(node, Node::Container { .. }) {
+                blocks_lines.extend(container_lines.next().unwrap_or_default());
+            }
+            node
+        })
+        .collect();
+    Ok((nodes, blocks_lines))
+}
+
+type Blocks<'a> = (Vec<Node<'a>>, Vec<Range<usize>>);
+
+// In the order they are first referred to, footnotes referred to by other footnotes included
+fn parse_footnotes<'a>(options: &Context<'a, '_>) -> Blocks<'a> {
+    let mut footnotes = Vec::new();
+    let mut lines = Vec::new();
+    while let Some((line, definition)) = options.footnote_definition(footnotes.len() + 1) {
+        footnotes.push(Node::Footnote(
+            footnotes.len() + 1,
+            parse_text(definition, options),
+        ));
+        lines.push(line..line + 1);
+    }
+    (footnotes, lines)
+}
+
+// Lines end with `\n`, `\r\n` or a lone `\r`, which is left out of them
+fn split_lines(s: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+
+    let mut pre_offset = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c == '\n' || c == '\r' {
+            lines.push((pre_offset, &s[pre_offset..idx]));
+            pre_offset = idx + 1;
+            if c == '\r' && chars.next_if(|(_, c)| *c == '\n').is_some() {
+                pre_offset += 1;
+            }
+        }
+    }
+    lines.push((pre_offset, &s[pre_offset..]));
+
+    lines
+}
+
+fn line_ending_len(s: &str, line_end: usize) -> usize {
+    if s[line_end..].starts_with("\r\n") {
+        2
+    } else {
+        1
+    }
+}
+
+fn is_definition(line: &str) -> bool {
+    let line = strip_block_prefixes(line);
+    try_parse_link_definition(line).is_some() || try_parse_footnote_definition(line).is_some()
+}
+
+fn is_htmd_comment(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("htmd:")
+}
+
+fn is_container_annonce(line: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    let line = line.trim();
+
+    let rest = line.trim_start_matches(':');
+    if line.len() - rest.len() < 3 {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let name_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (name, attrs) = rest.split_at(name_len);
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, parse_container_attrs(attrs)))
+}
+
+fn parse_container_attrs(mut attrs: &str) -> Vec<(&str, &str)> {
+    let mut parsed = Vec::new();
+
+    loop {
+        attrs = attrs.trim_start();
+        if attrs.is_empty() {
+            break;
+        }
+
+        let key_len = attrs
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(attrs.len());
+        let (key, rest) = attrs.split_at(key_len);
+
+        let Some(rest) = rest.strip_prefix('=') else {
+            parsed.push((key, ""));
+            attrs = rest;
+            continue;
+        };
+
+        let (value, rest) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let rest = &rest[1..];
+                let value_len = rest.find(quote).unwrap_or(rest.len());
+                (&rest[..value_len], rest.get(value_len + 1..).unwrap_or(""))
+            }
+            _ => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        if !key.is_empty() {
+            parsed.push((key, value));
+        }
+        attrs = rest;
+    }
+
+    parsed
+}
+
+fn is_container_close(line: &str) -> bool {
+    let line = line.trim();
+    line.len() >= 3 && line.chars().all(|c| c == ':')
+}
+
+fn find_container_end(lines: &[(usize, &str)], start: usize) -> usize {
+    let mut deepth = 0;
+    let mut codeblock_fence = None;
+
+    for (idx, (_, line)) in lines.iter().enumerate().skip(start) {
+        if let Some((fence, len, _)) = is_code_block_annonce(line) {
+            codeblock_fence = match codeblock_fence {
+                None => Some((fence, len)),
+                Some(fence) if is_code_block_close(line, fence) => None,
+                fence => fence,
+            };
+            continue;
+        }
+        if codeblock_fence.is_some() {
+            continue;
         }
 
-        let nodes = compacte_nodes(nodes);
-        MarkDown(Document { nodes })
+        if is_container_annonce(line).is_some() {
+            deepth += 1;
+        } else if is_container_close(line) {
+            deepth -= 1;
+            if deepth == 0 {
+                return idx;
+            }
+        }
     }
+
+    lines.len()
 }
 
 // =============================================== TEXT ===============================================
 
-fn parse_text(line: &str) -> Text {
+fn parse_table_delimiter(line: &str) -> Option<Vec<Option<Alignment>>> {
+    if !line.contains('|') {
+        return None;
+    }
+    table_cells(line)
+        .into_iter()
+        .map(|cell| {
+            let cell = cell.trim();
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || dashes.contains(|c| c != '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Some(Alignment::Center),
+                (true, false) => Some(Alignment::Left),
+                (false, true) => Some(Alignment::Right),
+                (false, false) => None,
+            })
+        })
+        .collect()
+}
+
+fn split_table_row<'a>(line: &'a str, options: &Context<'a, '_>) -> Vec<Text<'a>> {
+    table_cells(line)
+        .into_iter()
+        .map(|cell| {
+            let mut text = parse_text(cell.trim(), options);
+            unescape_code_pipes(&mut text, false);
+            text
+        })
+        .collect()
+}
+
+// As in GFM, `\|` is a pipe in the code spans of a cell too, where backslashes are literal otherwise
+fn unescape_code_pipes(text: &mut Text, in_code: bool) {
+    for frag in text.content.iter_mut() {
+        match frag {
+            TextFragment::Stylised(Style::Normal, s) if in_code && s.contains("\\|") => {
+                *s = s.replace("\\|", "|").into()
+            }
+            TextFragment::Group(style, text) => {
+                unescape_code_pipes(text, in_code || *style == Style::Code)
+            }
+            TextFragment::Link(alt, _, _) => unescape_code_pipes(alt, in_code),
+            _ => (),
+        }
+    }
+}
+
+// Splits on the pipes outside of code spans, leaving `\|` to `parse_text`
+fn table_cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut in_code = false;
+    let mut chars = line.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '`' => in_code = !in_code,
+            '|' if !in_code => {
+                cells.push(&line[cell_start..idx]);
+                cell_start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    // A trailing pipe closes the last cell rather than opening a new one
+    if cell_start < line.len() || cells.is_empty() {
+        cells.push(&line[cell_start..]);
+    }
+
+    cells
+}
+
+fn parse_text<'a>(line: &'a str, options: &Context<'a, '_>) -> Text<'a> {
+    parse_text_with(line, options, options.autolinks)
+}
+
+// The text of a link is parsed without bare autolinks, as a link can't hold another one and theirs
+// would take in the escapes written in the text
+fn parse_text_with<'a>(line: &'a str, options: &Context<'a, '_>, autolinks: bool) -> Text<'a> {
     let mut asterisks = [Queue::new(), Queue::new(), Queue::new()];
     let mut underscores = [Queue::new(), Queue::new(), Queue::new()];
     let mut backticks = [Queue::new(), Queue::new(), Queue::new()];
     let mut tildes = [Queue::new(), Queue::new(), Queue::new()];
+    let mut equals = [Queue::new(), Queue::new(), Queue::new()];
+    let mut carets = [Queue::new(), Queue::new(), Queue::new()];
+    // Runs that can open, waiting for a closing one to be paired with in their queue
+    let mut asterisk_openers = [Vec::new(), Vec::new(), Vec::new()];
+    let mut underscore_openers = [Vec::new(), Vec::new(), Vec::new()];
+    let mut tilde_openers = [Vec::new(), Vec::new(), Vec::new()];
+    let mut equal_openers = [Vec::new(), Vec::new(), Vec::new()];
+    let mut caret_openers = [Vec::new(), Vec::new(), Vec::new()];
 
     let mut links_images = Vec::new();
-    let mut escaped = Vec::new();
+    // Escaping backslashes and the spaces padding code spans, left out of the text
+    let mut removed = Vec::new();
+    let mut entities = Vec::new();
+    // Found once for the whole line, rather than looked for from every '[', escaped ones excepted
+    let mut closing_brackets = Vec::new();
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        if c == ']' && !escaped {
+            closing_brackets.push(idx);
+        }
+        escaped = c == '\\' && !escaped;
+    }
 
     let mut offset = 0;
 
@@ -57,187 +714,814 @@ fn parse_text(line: &str) -> Text {
             break;
         }
 
-        try_push_link_image_in(line, &mut chars, &mut offset, &mut links_images);
+        let previous_offset = offset;
 
-        try_push_prefixe_idx_in(&mut chars, &mut offset, '*', &mut asterisks);
-        try_push_prefixe_idx_in(&mut chars, &mut offset, '_', &mut underscores);
-        try_push_prefixe_idx_in(&mut chars, &mut offset, '`', &mut backticks);
-        try_push_prefixe_idx_in(&mut chars, &mut offset, '~', &mut tildes);
+        try_push_footnote_ref_in(
+            line,
+            &mut chars,
+            &mut offset,
+            &mut links_images,
+            &closing_brackets,
+            options,
+        );
+        try_push_link_image_in(
+            line,
+            &mut chars,
+            &mut offset,
+            &mut links_images,
+            &closing_brackets,
+            options,
+        );
+        try_push_autolink_in(line, &mut chars, &mut offset, &mut links_images);
+        if autolinks {
+            try_push_bare_autolink_in(line, &mut chars, &mut offset, &mut links_images);
+        }
+
+        try_push_prefixe_idx_in(
+            line,
+            &mut chars,
+            &mut offset,
+            '*',
+            &mut asterisk_openers,
+            &mut asterisks,
+            options.cjk_emphasis,
+        );
+        try_push_prefixe_idx_in(
+            line,
+            &mut chars,
+            &mut offset,
+            '_',
+            &mut underscore_openers,
+            &mut underscores,
+            options.cjk_emphasis,
+        );
+        if options.inline_code {
+            try_push_code_span_in(line, &mut chars, &mut offset, &mut backticks, &mut removed);
+        }
+        try_push_prefixe_idx_in(
+            line,
+            &mut chars,
+            &mut offset,
+            '~',
+            &mut tilde_openers,
+            &mut tildes,
+            options.cjk_emphasis,
+        );
+        try_push_prefixe_idx_in(
+            line,
+            &mut chars,
+            &mut offset,
+            '=',
+            &mut equal_openers,
+            &mut equals,
+            options.cjk_emphasis,
+        );
+        try_push_prefixe_idx_in(
+            line,
+            &mut chars,
+            &mut offset,
+            '^',
+            &mut caret_openers,
+            &mut carets,
+            options.cjk_emphasis,
+        );
+        try_push_entity_in(line, &mut chars, &mut offset, &mut entities);
 
-        while let Some( c) = chars.peek() && !['*', '_', '`', '~'].contains(c) {
+        // A lone '[', '!', '<' or '&' opening no link nor entity is plain text, as are backticks
+        // without code spans
+        if offset == previous_offset {
+            if let Some(c) = chars.next_if(|c| ['[', '!', '<', '&', '`'].contains(c)) {
+                offset += c.len_utf8();
+            }
+        }
+
+        while let Some(c) = chars.next_if(|c| {
+            !(['*', '_', '`', '~', '=', '^', '[', '!', '<', '&'].contains(c)
+                || (autolinks && bare_autolink_at(line, offset).is_some()))
+        }) {
             offset += c.len_utf8();
-            if *c == '\\' {
-                escaped.push(offset - c.len_utf8());
-                chars.next();
+            // Only ASCII punctuation is escaped, the backslash staying literal before anything else
+            if c == '\\' && chars.next_if(char::is_ascii_punctuation).is_some() {
+                removed.push(offset - 1);
+                offset += 1;
             }
-            chars.next();
         }
     }
 
-    let mut buffers = [asterisks, underscores, backticks, tildes];
-    let mut text = Text {
-        content: vec![TextFragment::Stylised(Style::Normal, line)],
-    };
-
+    let mut buffers = [asterisks, underscores, backticks, tildes, equals, carets];
+    let mut pairs = Vec::new();
     while let Some(((start, end), (x, y))) = pop_min2(&mut buffers) {
-        let span = Span::from_start_end(start, end);
-        match y {
+        // As in Pandoc, a subscript or a superscript doesn't hold spaces
+        let spaced = || line[start + x + 1..end].contains(char::is_whitespace);
+        let style = match y {
             // Asterisk * and underscore _
             0 | 1 => match x {
-                0 => text.style(x + 1, span, Style::Emphasis),
-                1 => text.style(x + 1, span, Style::Strong),
-                2 => {
-                    text.style(x + 1, span, Style::Emphasis | Style::Strong);
-                }
+                0 => Style::Emphasis,
+                1 => Style::Strong,
+                2 => Style::Emphasis | Style::Strong,
                 _ => unreachable!(),
             },
-            // Backtick `
-            2 => text.style(x + 1, span, Style::Code),
-            // Tilde ~
-            3 if x == 1 => text.style(x + 1, span, Style::Strikethrough),
-            _ => (),
-        }
+            // Backtick `, runs of more than three sharing the last queue
+            2 => {
+                let run = line[start..].len() - line[start..].trim_start_matches('`').len();
+                pairs.push(Delimited {
+                    style: Style::Code,
+                    start,
+                    end,
+                    len: run,
+                });
+                continue;
+            }
+            // Tilde ~, a single one makes a subscript, a double one strikes through and other runs
+            // stay literal
+            3 if x == 0 && !spaced() => Style::Sub,
+            3 if x == 1 && options.strikethrough => Style::Strikethrough,
+            // Equal =, only a double one highlights
+            4 if x == 1 => Style::Highlight,
+            // Caret ^, only a single one makes a superscript
+            5 if x == 0 && !spaced() => Style::Sup,
+            _ => continue,
+        };
+        pairs.push(Delimited {
+            style,
+            start,
+            end,
+            len: x + 1,
+        });
     }
+    let mut text = nest(line, nested_pairs(pairs));
+
+    let mut edits: Vec<(Span, Edit)> = links_images
+        .into_iter()
+        .map(|(span, frag)| (span, Edit::Replace(frag)))
+        .chain(
+            removed
+                .into_iter()
+                .map(|offset| (Span::new(offset, 1), Edit::Remove)),
+        )
+        .chain(
+            entities
+                .into_iter()
+                .map(|(span, c)| (span, Edit::Decode(c))),
+        )
+        .collect();
+    edits.sort_by_key(|(span, _)| span.offset);
+    text.edit(edits);
+
+    text
+}
 
-    for (span, frag) in links_images {
-        text.replace(span, frag)
+// A pair of delimiter runs of `len` characters, styling the text between them, the closing run
+// starting at `end`
+#[derive(Debug, Clone, Copy)]
+struct Delimited {
+    style: Style,
+    start: usize,
+    end: usize,
+    len: usize,
+}
+
+// A pair opened inside another one of a different delimiter and closed after it is dropped, its
+// delimiters staying literal, so that the pairs kept nest in each other
+fn nested_pairs(mut pairs: Vec<Delimited>) -> Vec<Delimited> {
+    pairs.sort_unstable_by_key(|pair| pair.end);
+
+    // The outermost pairs kept so far, as where they start and end, one after the other
+    let mut outermost: Vec<(usize, usize)> = Vec::new();
+    let mut kept = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        let idx = outermost.partition_point(|(start, _)| *start < pair.start);
+        if idx > 0 && outermost[idx - 1].1 > pair.start {
+            continue;
+        }
+        outermost.truncate(idx);
+        outermost.push((pair.start, pair.end + pair.len));
+        kept.push(pair);
     }
+    kept
+}
+
+// The line as a tree of groups, one per pair, holding the text between its delimiters and the
+// delimiters themselves as modifiers
+fn nest(line: &str, mut pairs: Vec<Delimited>) -> Text<'_> {
+    // The outer pairs first among those starting together
+    pairs.sort_unstable_by_key(|pair| (pair.start, core::cmp::Reverse(pair.end)));
+    let mut pairs = pairs.into_iter().peekable();
+
+    // The root comes first, followed by the groups opened inside each other
+    let mut groups: Vec<(Option<Delimited>, Vec<TextFragment>)> = vec![(None, Vec::new())];
+    let mut offset = 0;
+    loop {
+        while let (Some(pair), _) = groups.last().unwrap() {
+            if pair.end + pair.len != offset {
+                break;
+            }
+            let (pair, content) = groups.pop().unwrap();
+            groups
+                .last_mut()
+                .unwrap()
+                .1
+                .push(TextFragment::Group(pair.unwrap().style, Text { content }));
+        }
+        while let Some(pair) = pairs.next_if(|pair| pair.start == offset) {
+            groups.push((Some(pair), Vec::new()));
+        }
+        if offset == line.len() {
+            break;
+        }
 
-    for escape_char in escaped {
-        text.remove(Span::new(escape_char, 1))
+        let (next, style) = match groups.last().unwrap().0 {
+            Some(pair) if offset < pair.start + pair.len => {
+                (pair.start + pair.len, Style::Modifier)
+            }
+            Some(pair) if offset >= pair.end => (pair.end + pair.len, Style::Modifier),
+            Some(pair) => (pair.end, Style::Normal),
+            None => (line.len(), Style::Normal),
+        };
+        let next = pairs.peek().map_or(next, |pair| pair.start.min(next));
+        groups
+            .last_mut()
+            .unwrap()
+            .1
+            .push(TextFragment::Stylised(style, line[offset..next].into()));
+        offset = next;
     }
 
-    text
+    Text {
+        content: groups.pop().unwrap().1,
+    }
 }
 
-use std::iter::Peekable;
+use core::iter::Peekable;
 fn try_push_link_image_in<'a>(
     line: &'a str,
-    text: &mut Peekable<impl Iterator<Item = char> + Clone>,
+    text: &mut Peekable<impl Iterator<Item = char>>,
     offset: &mut usize,
     buffer: &mut Vec<(Span, TextFragment<'a>)>,
+    closing_brackets: &[usize],
+    options: &Context<'a, '_>,
 ) {
-    let c = text.peek().cloned();
-    if c.is_none() || !['!', '['].contains(&c.unwrap()) {
+    // Scanned by offsets, so that a failed attempt costs nothing to the iterator
+    let rest = &line[*offset..];
+    let is_image = rest.starts_with('!');
+    let mut link_offset = usize::from(is_image);
+
+    // First '['
+    if !rest[link_offset..].starts_with('[') {
         return;
     }
+    link_offset += '['.len_utf8();
 
-    let mut text_cloned = text.clone();
-    let mut link_offset = 0;
+    // Alt
+    let Some(alt_len) = next_closing_bracket(closing_brackets, *offset + link_offset) else {
+        return;
+    };
+    let alt = &rest[link_offset..link_offset + alt_len];
+    link_offset += alt_len + ']'.len_utf8();
+
+    let (link, size, title) = if rest[link_offset..].starts_with('(') {
+        // First '('
+        link_offset += '('.len_utf8();
+
+        // Link, title and last ')'
+        let Some((link, size, title, len)) = split_link_title(&rest[link_offset..], is_image)
+        else {
+            return;
+        };
+        link_offset += len;
+        (link, size, title)
+    } else {
+        // A reference as `[label]`, or `[]` and nothing at all for the alt to be the label
+        let mut label = alt;
+        if rest[link_offset..].starts_with('[') {
+            link_offset += '['.len_utf8();
 
-    let is_image = c.unwrap() == '!';
+            let Some(label_len) = next_closing_bracket(closing_brackets, *offset + link_offset)
+            else {
+                return;
+            };
+            if label_len > 0 {
+                label = &rest[link_offset..link_offset + label_len];
+            }
+            link_offset += label_len + ']'.len_utf8();
+        }
+
+        match options.link_definition(label) {
+            Some((link, title)) => (Cow::Borrowed(link), ImageSize::default(), title),
+            None => {
+                if !core::ptr::eq(label, alt) {
+                    options.refer_to_undefined(label);
+                }
+                return;
+            }
+        }
+    };
+
+    // "Return"
+    let span = Span::new(*offset, link_offset);
     if is_image {
-        text_cloned.next();
-        link_offset += '!'.len_utf8();
+        // A disabled image stays literal as a whole, rather than leaving a link behind
+        if options.images {
+            buffer.push((
+                span,
+                TextFragment::Image(alt.into(), link, title.map(Into::into), size),
+            ))
+        }
+    } else {
+        // A link can't hold another one, as an url written as the alt would be
+        let mut alt = parse_text_with(alt, options, false);
+        alt.content = alt
+            .content
+            .into_iter()
+            .flat_map(|frag| match frag {
+                TextFragment::Link(alt, _, _) => alt.content,
+                frag => vec![frag],
+            })
+            .collect();
+        buffer.push((span, TextFragment::Link(alt, link, title.map(Into::into))))
     }
 
-    // First '['
-    if let Some(c) = text_cloned.next() && c == '[' {
-        link_offset += '['.len_utf8();
-    } else {
-        return;
+    let end = *offset + link_offset;
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
     }
+}
 
-    // Alt
-    // let mut alt = "";
-    let mut alt_span = Span::new(*offset + link_offset, 0);
-    let mut succes = false;
-    for c in text_cloned.by_ref() {
-        link_offset += c.len_utf8();
-        if c != ']' {
-            alt_span.extend(c.len_utf8())
+// The distance from `from` to the first ']' of the line at or after it
+fn next_closing_bracket(closing_brackets: &[usize], from: usize) -> Option<usize> {
+    let idx = closing_brackets.partition_point(|bracket| *bracket < from);
+    closing_brackets.get(idx).map(|bracket| bracket - from)
+}
+
+// `url =WxH "title")` with the size of `sized` images and the title being optional and the url
+// holding spaces only between `<>`, along the length up to the closing ')' included
+fn split_link_title(
+    s: &str,
+    sized: bool,
+) -> Option<(Cow<'_, str>, ImageSize, Option<&str>, usize)> {
+    let rest = s.trim_start_matches(' ');
+    let (link, rest) = match rest.strip_prefix('<') {
+        Some(rest) => rest.split_once('>')?,
+        None => split_destination(rest),
+    };
+
+    // An invalid size is dropped, rather than left in the url
+    let mut rest = rest.trim_start_matches(' ');
+    let mut size = ImageSize::default();
+    if let Some(size_rest) = rest.strip_prefix('=').filter(|_| sized) {
+        let len = size_rest.find([' ', ')']).unwrap_or(size_rest.len());
+        size = parse_image_size(&size_rest[..len]).unwrap_or_default();
+        rest = size_rest[len..].trim_start_matches(' ');
+    }
+
+    let (title, rest) = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let (title, rest) = rest[quote.len_utf8()..].split_once(quote)?;
+            (Some(title), rest.trim_start_matches(' '))
+        }
+        _ => (None, rest),
+    };
+
+    let rest = rest.strip_prefix(')')?;
+    Some((unescape(link), size, title, s.len() - rest.len()))
+}
+
+// `200x100`, either side being possibly left out
+fn parse_image_size(s: &str) -> Option<ImageSize> {
+    let (width, height) = s.split_once('x')?;
+    let dimension = |n: &str| -> Option<Option<u32>> {
+        if n.is_empty() {
+            Some(None)
+        } else if n.chars().all(|c| c.is_ascii_digit()) {
+            n.parse().ok().map(Some)
         } else {
-            succes = true;
-            break;
+            None
+        }
+    };
+    let size = ImageSize {
+        width: dimension(width)?,
+        height: dimension(height)?,
+    };
+    (!size.is_empty()).then_some(size)
+}
+
+// The url goes up to a space or the ')' closing the link, as `wiki/Rust_(language)` holds
+// balanced or escaped parentheses
+fn split_destination(s: &str) -> (&str, &str) {
+    let mut deepth = 0;
+    let mut chars = s.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' if s[idx + 1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                chars.next();
+            }
+            '(' => deepth += 1,
+            ')' if deepth > 0 => deepth -= 1,
+            ')' | ' ' => return s.split_at(idx),
+            _ => (),
         }
     }
-    if !succes {
+    (s, "")
+}
+
+// Drops the backslashes escaping ASCII punctuation
+fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.next_if(|next| c == '\\' && next.is_ascii_punctuation()) {
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+// `[^label]`, staying literal when no footnote is defined with that label
+fn try_push_footnote_ref_in<'a>(
+    line: &'a str,
+    text: &mut Peekable<impl Iterator<Item = char>>,
+    offset: &mut usize,
+    buffer: &mut Vec<(Span, TextFragment<'a>)>,
+    closing_brackets: &[usize],
+    options: &Context<'a, '_>,
+) {
+    if !line[*offset..].starts_with("[^") {
         return;
     }
-    let alt = alt_span.fetch(line).unwrap();
+    let start = *offset + "[^".len();
+    let Some(label_len) = next_closing_bracket(closing_brackets, start) else {
+        return;
+    };
+    let label = &line[start..start + label_len];
+    if label.is_empty() || label.contains(|c: char| c.is_whitespace() || c == '[') {
+        return;
+    }
+    let Some(number) = options.footnote_number(label) else {
+        return;
+    };
 
-    // First '('
-    if let Some(c) = text_cloned.next() && c == '(' {
-        link_offset += '('.len_utf8();
+    let len = "[^".len() + label.len() + "]".len();
+    buffer.push((
+        Span::new(*offset, len),
+        TextFragment::FootnoteRef(label.into(), number),
+    ));
+
+    let end = *offset + len;
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
+    }
+}
+
+// `<scheme:uri>` or `<local@domain>`, the url being its own alt
+fn try_push_autolink_in<'a>(
+    line: &'a str,
+    text: &mut Peekable<impl Iterator<Item = char>>,
+    offset: &mut usize,
+    buffer: &mut Vec<(Span, TextFragment<'a>)>,
+) {
+    if text.peek() != Some(&'<') {
+        return;
+    }
+    let Some((url, _)) = line[*offset + '<'.len_utf8()..].split_once('>') else {
+        return;
+    };
+
+    let link = if is_uri(url) {
+        Cow::Borrowed(url)
+    } else if is_email(url) {
+        Cow::Owned(format!("mailto:{url}"))
     } else {
         return;
+    };
+
+    let len = "<".len() + url.len() + ">".len();
+    let alt = Text {
+        content: vec![TextFragment::Stylised(Style::Normal, url.into())],
+    };
+    buffer.push((Span::new(*offset, len), TextFragment::Link(alt, link, None)));
+
+    let end = *offset + len;
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
     }
+}
 
-    // Link
-    let mut link_span = Span::new(*offset + link_offset, 0);
-    let mut succes = false;
-    for c in text_cloned.by_ref() {
-        link_offset += c.len_utf8();
-        if c != ')' {
-            link_span.extend(c.len_utf8())
-        } else {
-            succes = true;
-            break;
+fn try_push_bare_autolink_in<'a>(
+    line: &'a str,
+    text: &mut Peekable<impl Iterator<Item = char>>,
+    offset: &mut usize,
+    buffer: &mut Vec<(Span, TextFragment<'a>)>,
+) {
+    let Some((url, link)) = bare_autolink_at(line, *offset) else {
+        return;
+    };
+
+    let alt = Text {
+        content: vec![TextFragment::Stylised(Style::Normal, url.into())],
+    };
+    buffer.push((
+        Span::new(*offset, url.len()),
+        TextFragment::Link(alt, link, None),
+    ));
+
+    let end = *offset + url.len();
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
+    }
+}
+
+// A `http://`, `https://` or `www.` url, or an email, starting a word. The punctuation ending a
+// sentence and unbalanced ')' are left out
+fn bare_autolink_at(line: &str, offset: usize) -> Option<(&str, Cow<'_, str>)> {
+    let is_word = |c: char| c.is_alphanumeric() || ['.', '+', '-', '_'].contains(&c);
+    if line[..offset].chars().next_back().is_some_and(is_word) {
+        return None;
+    }
+
+    let rest = &line[offset..];
+    if !rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    // The end of an url is only looked for once its start is known, as this is tried at every word
+    let url = || {
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '<')
+            .unwrap_or(rest.len());
+        trim_url_end(&rest[..end])
+    };
+
+    if let Some(scheme) = ["http://", "https://"]
+        .into_iter()
+        .find(|scheme| rest.starts_with(scheme))
+    {
+        let url = url();
+        let after_scheme = &url[scheme.len()..];
+        is_domain(url_host(after_scheme)).then_some((url, Cow::Borrowed(url)))
+    } else if rest.starts_with("www.") {
+        let url = url();
+        is_domain(url_host(url)).then(|| (url, Cow::Owned(format!("http://{url}"))))
+    } else {
+        let local_len = rest.find(|c: char| !c.is_ascii() || !is_word(c))?;
+        let domain = rest[local_len..].strip_prefix('@')?;
+        let domain_len = domain
+            .find(|c: char| !(c.is_ascii_alphanumeric() || ['.', '-', '_'].contains(&c)))
+            .unwrap_or(domain.len());
+        let domain = domain[..domain_len].trim_end_matches('.');
+
+        let email = &rest[..local_len + "@".len() + domain.len()];
+        (!domain.ends_with(['-', '_']) && is_domain(domain))
+            .then(|| (email, Cow::Owned(format!("mailto:{email}"))))
+    }
+}
+
+fn url_host(url: &str) -> &str {
+    url.split(['/', '?', '#', ':']).next().unwrap_or_default()
+}
+
+fn trim_url_end(mut url: &str) -> &str {
+    loop {
+        let trimmed = url.trim_end_matches(['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if trimmed.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
         }
+        url = trimmed;
     }
-    if !succes {
+}
+
+// Labels of letters, digits, '-' and '_' separated by dots, with '_' out of the last two
+fn is_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || ['-', '_'].contains(&c))
+        })
+        && labels
+            .iter()
+            .rev()
+            .take(2)
+            .all(|label| !label.contains('_'))
+}
+
+// A scheme of 2 to 32 characters, then anything but spaces and angle brackets
+fn is_uri(s: &str) -> bool {
+    let Some((scheme, rest)) = s.split_once(':') else {
+        return false;
+    };
+
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ['+', '.', '-'].contains(&c))
+        && !rest.contains(|c: char| c.is_whitespace() || c.is_control() || c == '<')
+}
+
+fn is_email(s: &str) -> bool {
+    let Some((local, domain)) = s.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn try_push_entity_in(
+    line: &str,
+    text: &mut Peekable<impl Iterator<Item = char>>,
+    offset: &mut usize,
+    buffer: &mut Vec<(Span, char)>,
+) {
+    let Some((c, len)) = entity_at(&line[*offset..]) else {
         return;
+    };
+    buffer.push((Span::new(*offset, len), c));
+
+    let end = *offset + len;
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
     }
-    let link = link_span.fetch(line).unwrap();
+}
 
-    // "Return"
-    let span = Span::new(*offset, link_offset);
-    if is_image {
-        buffer.push((span, TextFragment::Image(alt, link)))
-    } else {
-        buffer.push((span, TextFragment::Link(alt, link)))
+// A code span runs up to the next backtick run of the same length and nothing inside is parsed,
+// a run closed by none stays literal
+fn try_push_code_span_in(
+    line: &str,
+    text: &mut Peekable<impl Iterator<Item = char>>,
+    offset: &mut usize,
+    buffers: &mut [Queue<usize>; 3],
+    removed: &mut Vec<usize>,
+) {
+    let backtick_run =
+        |start: usize| line[start..].len() - line[start..].trim_start_matches('`').len();
+
+    let run = backtick_run(*offset);
+    if run == 0 {
+        return;
+    }
+
+    let mut search = *offset + run;
+    let mut end = *offset + run;
+    while let Some(found) = line[search..].find('`') {
+        let closing = search + found;
+        let closing_run = backtick_run(closing);
+        if closing_run == run {
+            buffers[(run - 1).min(2)].push(*offset);
+            buffers[(run - 1).min(2)].push(closing);
+            end = closing + run;
+
+            // One space on each side lets the code start or end with a backtick
+            let code = &line[*offset + run..closing];
+            if code.len() >= 2
+                && code.starts_with(' ')
+                && code.ends_with(' ')
+                && !code.trim_start_matches(' ').is_empty()
+            {
+                removed.push(*offset + run);
+                removed.push(closing - 1);
+            }
+            break;
+        }
+        search = closing + closing_run;
+    }
+
+    while *offset < end {
+        *offset += text.next().map_or(1, char::len_utf8);
     }
-    *text = text_cloned;
-    *offset += link_offset;
 }
 
 fn try_push_prefixe_idx_in(
+    line: &str,
     text: &mut Peekable<impl Iterator<Item = char>>,
     offset: &mut usize,
     prefixe: char,
+    openers: &mut [Vec<usize>; 3],
     buffers: &mut [Queue<usize>; 3],
+    cjk: bool,
 ) {
     let mut occurence = 0;
     let mut prefixe_offset = 0;
-    while let Some(c) = text.peek() && *c == prefixe {
+    while let Some(c) = text.next_if_eq(&prefixe) {
         occurence += 1;
         prefixe_offset += c.len_utf8();
-
-        text.next();
     }
 
-    match occurence {
-        0 => (),
-        1 => buffers[0].push(*offset),
-        2 => buffers[1].push(*offset),
-        _ => buffers[2].push(*offset),
+    let idx = match occurence {
+        0 => return,
+        1 => 0,
+        2 => 1,
+        _ => 2,
+    };
+
+    let (can_open, can_close) = flanking(line, *offset, prefixe_offset, prefixe, cjk);
+    let opener = if can_close { openers[idx].pop() } else { None };
+    if let Some(start) = opener {
+        buffers[idx].push(start);
+        buffers[idx].push(*offset);
+        // Runs opened inside the pair can't close after it anymore
+        for openers in openers.iter_mut() {
+            openers.retain(|opener| *opener < start);
+        }
+    } else if can_open {
+        openers[idx].push(*offset);
     }
     *offset += prefixe_offset;
 }
 
+// Whether a delimiter run can open and close, following CommonMark's flanking rules. An underscore
+// run also never opens nor closes inside a word. With `cjk`, a CJK character outside the run counts
+// as a space does, as CJK text doesn't part its words with spaces.
+fn flanking(line: &str, offset: usize, len: usize, prefixe: char, cjk: bool) -> (bool, bool) {
+    let before = line[..offset].chars().next_back().unwrap_or(' ');
+    let after = line[offset + len..].chars().next().unwrap_or(' ');
+    let is_punctuation = |c: char| !c.is_alphanumeric() && !c.is_whitespace();
+    let is_outside = |c: char| c.is_whitespace() || is_punctuation(c) || (cjk && is_cjk(c));
+
+    let left_flanking = !after.is_whitespace() && (!is_punctuation(after) || is_outside(before));
+    let right_flanking = !before.is_whitespace() && (!is_punctuation(before) || is_outside(after));
+
+    if prefixe == '_' {
+        (
+            left_flanking && (!right_flanking || is_punctuation(before)),
+            right_flanking && (!left_flanking || is_punctuation(after)),
+        )
+    } else {
+        (left_flanking, right_flanking)
+    }
+}
+
+// Han, kana, hangul, and the CJK punctuation and fullwidth forms
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{2E80}'..='\u{2FDF}'
+            | '\u{3000}'..='\u{303F}'
+            | '\u{3040}'..='\u{30FF}'
+            | '\u{3100}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF00}'..='\u{FFEF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
 // =============================================== LINE ===============================================
 
 fn parse_line<'a>(
     s: &'a str,
     offset: usize,
     line: &'a str,
-    codeblock: &mut Option<CodeBlock<'a>>,
+    codeblock: &mut Option<((char, usize), CodeBlock<'a>)>,
+    options: &Context<'a, '_>,
 ) -> Option<Node<'a>> {
     match codeblock {
-        Some(codeblock_inner) => {
-            if let Some(language) = is_code_block_annonce(line) && language.is_empty() {
-                Some(Node::CodeBlock(codeblock.take().unwrap()))
-            } else {
-                codeblock_inner.code.extend(line.len() + 1);
-                None
-            }
+        Some((fence, _)) if is_code_block_close(line, *fence) => Some(Node::CodeBlock(
+            codeblock.take().unwrap().1.normalize_line_endings(),
+        )),
+        Some((_, codeblock_inner)) => {
+            codeblock_inner
+                .code
+                .extend(line.len() + line_ending_len(s, offset + line.len()));
+            None
         }
         None => {
-            if let Some(language) = is_code_block_annonce(line) {
-                *codeblock = Some(CodeBlock::new(
-                    s,
-                    language,
-                    Span::new(offset + line.len() + 1, 0),
+            if let Some((fence, len, info)) = is_code_block_annonce(line) {
+                let (language, meta, line_start) = parse_fence_info(info);
+                *codeblock = Some((
+                    (fence, len),
+                    CodeBlock::new(
+                        s,
+                        language,
+                        Span::new(
+                            offset + line.len() + line_ending_len(s, offset + line.len()),
+                            0,
+                        ),
+                    )
+                    .with_meta(meta)
+                    .with_line_start(line_start),
                 ));
                 return None;
             }
@@ -246,99 +1530,298 @@ fn parse_line<'a>(
                 return Some(Node::LineBreak);
             }
 
-            if let Some(node) = try_parse_header(line) {
+            if let Some(node) = try_parse_quote(line, options) {
                 return Some(node);
             }
 
-            if let Some(node) = try_parse_unordered_list(line) {
+            if let Some(node) = try_parse_header(line, options) {
                 return Some(node);
             }
 
-            if let Some(node) = try_parse_ordered_list(line) {
+            // `- - -` and `* * *` are rules rather than items
+            if let Some(node) = try_parse_rule(line) {
                 return Some(node);
             }
 
-            if let Some(node) = try_parse_rule(line) {
+            if let Some(node) = try_parse_unordered_list(line, options) {
                 return Some(node);
             }
 
-            Some(Node::Paragraphe(parse_text(line)))
+            if let Some(node) = try_parse_ordered_list(line, options) {
+                return Some(node);
+            }
+
+            Some(Node::Paragraphe(parse_paragraphe_line(line, options)))
+        }
+    }
+}
+
+// Two trailing spaces or a backslash end the line with a hard break
+fn parse_paragraphe_line<'a>(line: &'a str, options: &Context<'a, '_>) -> Text<'a> {
+    let without_spaces = line.trim_end_matches(' ');
+    let backslashes = without_spaces.len() - without_spaces.trim_end_matches('\\').len();
+
+    let content = if line.len() - without_spaces.len() >= 2 {
+        without_spaces
+    } else if backslashes % 2 == 1 && without_spaces.len() == line.len() {
+        &line[..line.len() - 1]
+    } else {
+        return parse_text(line, options);
+    };
+
+    let mut text = parse_text(content, options);
+    // The backslash is kept as a modifier, to be written back if the line ends the block
+    if content.len() < without_spaces.len() {
+        text.content
+            .push(TextFragment::Stylised(Style::Modifier, "\\".into()));
+    }
+    text.content.push(TextFragment::HardBreak);
+    text
+}
+
+fn try_parse_quote<'a>(line: &'a str, options: &Context<'a, '_>) -> Option<Node<'a>> {
+    let mut text = line.trim_start().strip_prefix('>')?;
+    let mut deepth = 1;
+    while let Some(rest) = text.trim_start().strip_prefix('>') {
+        text = rest;
+        deepth += 1;
+    }
+
+    Some(Node::Quote(
+        deepth,
+        parse_paragraphe_line(text.trim_start(), options),
+    ))
+}
+
+fn strip_code_indent(line: &str, tab_width: usize) -> Option<&str> {
+    let mut width = 0;
+    for (idx, c) in line.char_indices() {
+        if width >= tab_width {
+            return Some(&line[idx..]);
         }
+        width = match c {
+            '\t' => next_tab_stop(width, tab_width),
+            ' ' => width + 1,
+            _ => return None,
+        };
+    }
+    (width >= tab_width).then_some("")
+}
+
+// `===` underlines a `<h1>` and `---` a `<h2>`
+fn is_setext_underline(line: &str) -> Option<usize> {
+    let line = line.trim();
+
+    if !line.is_empty() && line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !line.is_empty() && line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
     }
 }
 
-fn try_parse_header(line: &str) -> Option<Node> {
+fn try_parse_header<'a>(line: &'a str, options: &Context<'a, '_>) -> Option<Node<'a>> {
+    let (hierachy, text) = strip_header_marker(line, options)?;
+    let text = strip_closing_hashes(text.trim());
+    Some(Node::Header(hierachy, parse_text(text, options)))
+}
+
+fn strip_header_marker<'a>(line: &'a str, options: &Context<'_, '_>) -> Option<(usize, &'a str)> {
     let line = line.trim();
 
     let text = line.trim_start_matches('#');
-    if text.len() == line.len() {
+    let hierachy = line.len() - text.len();
+    if hierachy == 0 || hierachy > options.max_header_level.min(6) {
         return None;
     }
+    if !text.is_empty() && !text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some((hierachy, text))
+}
 
-    if let Some(text) = text.strip_prefix(char::is_whitespace) {
-        let hierachy = line.len() - text.len() - 1;
+// A line opening another block, which ends a table rather than being one of its rows. Only
+// markers are looked at, as parsing the text would register its footnote references
+fn is_block_start(line: &str, options: &Context<'_, '_>) -> bool {
+    line.trim_start().starts_with('>')
+        || strip_header_marker(line, options).is_some()
+        || try_parse_rule(line).is_some()
+        || is_list_item(line)
+        || is_code_block_annonce(line).is_some()
+        || is_container_annonce(line).is_some()
+        || is_container_close(line)
+}
 
-        let text = parse_text(&line[hierachy + 1..]);
-        Some(Node::Header(hierachy.min(5), text))
+// `## title ##`, the closing hashes needing a space before them to not be part of the title
+fn strip_closing_hashes(text: &str) -> &str {
+    let content = text.trim_end_matches('#');
+    if content.is_empty() {
+        content
+    } else if content.ends_with(char::is_whitespace) {
+        content.trim_end()
     } else {
-        None
+        text
     }
 }
 
-fn try_parse_unordered_list(line: &str) -> Option<Node> {
-    let deepth = calcule_deepth(line);
-    let line = line.trim();
+fn is_header_continuation(line: &str) -> bool {
+    line.starts_with(char::is_whitespace) && !line.trim().is_empty()
+}
 
-    let text = line
-        .strip_prefix("- ")
+fn try_parse_unordered_list<'a>(line: &'a str, options: &Context<'a, '_>) -> Option<Node<'a>> {
+    let deepth = calcule_deepth(line, options.tab_width);
+    let text = strip_unordered_marker(line.trim_start());
+
+    text.map(|text| match try_strip_task_marker(text.trim()) {
+        Ok((checked, task)) if options.task_lists => Node::List(
+            ListKind::Task(deepth, checked),
+            vec![parse_text(task, options)],
+            false,
+        ),
+        _ => Node::List(
+            ListKind::Unordere(deepth),
+            vec![parse_text(text.trim(), options)],
+            false,
+        ),
+    })
+}
+
+fn strip_unordered_marker(line: &str) -> Option<&str> {
+    line.strip_prefix("- ")
         .or(line.strip_prefix("+ "))
-        .or(line.strip_prefix("* "));
+        .or(line.strip_prefix("* "))
+}
 
-    text.map(|text| Node::List(ListKind::Unordere(deepth), parse_text(text)))
+// Only looks at the marker, as parsing the text would register its footnote references
+fn is_list_item(line: &str) -> bool {
+    let line = line.trim_start();
+    strip_unordered_marker(line).is_some() || strip_ordered_marker(line).is_some()
 }
 
-fn try_parse_ordered_list(line: &str) -> Option<Node> {
-    let deepth = calcule_deepth(line);
-    let line = line.trim();
+fn try_strip_task_marker(text: &str) -> Result<(bool, &str), &str> {
+    let (checked, task) = if let Some(task) = text.strip_prefix("[ ]") {
+        (false, task)
+    } else if let Some(task) = text.strip_prefix("[x]").or(text.strip_prefix("[X]")) {
+        (true, task)
+    } else {
+        return Err(text);
+    };
+
+    if task.is_empty() || task.starts_with(char::is_whitespace) {
+        Ok((checked, task.trim_start()))
+    } else {
+        Err(text)
+    }
+}
+
+fn try_parse_ordered_list<'a>(line: &'a str, options: &Context<'a, '_>) -> Option<Node<'a>> {
+    let deepth = calcule_deepth(line, options.tab_width);
+    let (delimiter, text) = strip_ordered_marker(line.trim_start())?;
+
+    Some(Node::List(
+        ListKind::Oredred(deepth, delimiter),
+        vec![parse_text(text.trim(), options)],
+        false,
+    ))
+}
 
+fn strip_ordered_marker(line: &str) -> Option<(char, &str)> {
     let text = line.trim_start_matches(char::is_numeric);
     if text.len() == line.len() {
         return None;
     }
 
-    let text = text.strip_prefix(". ");
+    let delimiter = text.chars().next().filter(|c| ['.', ')'].contains(c))?;
+    text[1..].strip_prefix(' ').map(|text| (delimiter, text))
+}
 
-    text.map(|text| Node::List(ListKind::Oredred(deepth), parse_text(text)))
+// Tabs expanding up to the next multiple of `tab_width`, as `  \t` and `\t` are as wide
+fn indent_width(line: &str, tab_width: usize) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |width, c| {
+            if c == '\t' {
+                next_tab_stop(width, tab_width)
+            } else {
+                width + 1
+            }
+        })
 }
 
-fn calcule_deepth(line: &str) -> usize {
-    let mut tab_occ = 0;
-    let mut space_occ = 0;
-    let mut chars = line.chars();
-    while let Some(c) = chars.next() && c.is_whitespace() {
-        if c == '\t' {
-            tab_occ += 1
-        } else {
-            space_occ += 1
-        }
-    }
+fn next_tab_stop(width: usize, tab_width: usize) -> usize {
+    (width / tab_width.max(1) + 1) * tab_width.max(1)
+}
+
+fn list_item_indent(line: &str, tab_width: usize) -> usize {
+    let marker = line.trim_start();
+    indent_width(line, tab_width) + marker.find(' ').map_or(marker.len(), |len| len + 1)
+}
 
-    tab_occ + (space_occ / 4)
+fn calcule_deepth(line: &str, tab_width: usize) -> usize {
+    indent_width(line, tab_width) / tab_width.max(1)
 }
 
-fn is_code_block_annonce(line: &str) -> Option<&str> {
+// A fence is a run of at least three backticks or tildes, returned along its length and the info
+// string following it
+fn is_code_block_annonce(line: &str) -> Option<(char, usize, &str)> {
     let line = line.trim();
 
-    let language = line.trim_start_matches('`');
+    let fence = line.chars().next().filter(|c| ['`', '~'].contains(c))?;
+    let language = line.trim_start_matches(fence);
+    let len = line.len() - language.len();
 
-    if line.len() - language.len() == 3 {
-        Some(language)
+    // A backtick in the info string makes the line a code span instead
+    if len >= 3 && !(fence == '`' && language.contains('`')) {
+        Some((fence, len, language))
     } else {
         None
     }
 }
 
-fn try_parse_rule(line: &str) -> Option<Node> {
+// Only a fence of the same character, at least as long and without info string closes a code block
+fn is_code_block_close(line: &str, (fence, len): (char, usize)) -> bool {
+    matches!(
+        is_code_block_annonce(line),
+        Some((closing_fence, closing_len, info))
+            if closing_fence == fence && closing_len >= len && info.is_empty()
+    )
+}
+
+// Splits the info string into its first word, the language, and the rest of it, after taking off
+// a `start` line number written either `rust {start=10}` or `rust:10`
+fn parse_fence_info(info: &str) -> (&str, &str, usize) {
+    let (info, line_start) = split_line_start(info.trim());
+    let (language, meta) = info
+        .split_once(char::is_whitespace)
+        .map_or((info, ""), |(language, meta)| (language, meta.trim()));
+    (language, meta, line_start)
+}
+
+fn split_line_start(info: &str) -> (&str, usize) {
+    let attr = info.find("{start=").and_then(|attr_start| {
+        let attr_len = info[attr_start..].find('}')?;
+        let line_start = info[attr_start + "{start=".len()..attr_start + attr_len]
+            .parse()
+            .ok()?;
+        Some((attr_start, line_start))
+    });
+    if let Some((attr_start, line_start)) = attr {
+        return (info[..attr_start].trim_end(), line_start);
+    }
+
+    if let Some((language, Ok(line_start))) = info
+        .split_once(':')
+        .map(|(language, line_start)| (language, line_start.parse()))
+    {
+        return (language, line_start);
+    }
+
+    (info, 1)
+}
+
+fn try_parse_rule(line: &str) -> Option<Node<'_>> {
     let line = line.trim();
 
     let mut character = None;
@@ -0,0 +1,62 @@
+use alloc::{borrow::Cow, vec::Vec};
+
+use unicode_normalization::{is_nfc, is_nfkc, UnicodeNormalization};
+
+use super::options::Normalization;
+use crate::document::{Node, Text, TextFragment};
+
+// Runs on the parsed fragments rather than on the source, so the offsets used while
+// styling always match the text they were computed on
+pub(super) fn normalize<'a>(
+    mut nodes: Vec<Node<'a>>,
+    form: Option<Normalization>,
+) -> Vec<Node<'a>> {
+    if let Some(form) = form {
+        normalize_nodes(&mut nodes, form);
+    }
+    nodes
+}
+
+fn normalize_nodes(nodes: &mut [Node], form: Normalization) {
+    for node in nodes {
+        match node {
+            Node::Header(_, text)
+            | Node::Paragraphe(text)
+            | Node::Quote(_, text)
+            | Node::Footnote(_, text) => normalize_text(text, form),
+            Node::List(_, paragraphs, _) => {
+                for text in paragraphs {
+                    normalize_text(text, form)
+                }
+            }
+            Node::Table { header, rows, .. } => {
+                for cell in header.iter_mut().chain(rows.iter_mut().flatten()) {
+                    normalize_text(cell, form)
+                }
+            }
+            Node::Container { children, .. } => normalize_nodes(children, form),
+            Node::CodeBlock(_) | Node::LineBreak | Node::Rule => (),
+        }
+    }
+}
+
+fn normalize_text(text: &mut Text, form: Normalization) {
+    for fragment in text.content.iter_mut() {
+        match fragment {
+            TextFragment::Stylised(_, s) => normalize_str(s, form),
+            TextFragment::Group(_, text) | TextFragment::Link(text, _, _) => {
+                normalize_text(text, form)
+            }
+            TextFragment::Image(alt, _, _, _) => normalize_str(alt, form),
+            TextFragment::FootnoteRef(_, _) | TextFragment::HardBreak => (),
+        }
+    }
+}
+
+fn normalize_str(s: &mut Cow<str>, form: Normalization) {
+    match form {
+        Normalization::Nfc if !is_nfc(s) => *s = Cow::Owned(s.nfc().collect()),
+        Normalization::Nfkc if !is_nfkc(s) => *s = Cow::Owned(s.nfkc().collect()),
+        _ => (),
+    }
+}
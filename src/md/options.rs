@@ -0,0 +1,58 @@
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    /// Columns of indentation making a list nesting level or an indented code block.
+    pub tab_width: usize,
+    /// Parse `~~text~~` as strikethrough rather than literal tildes. `~text~` is a subscript either
+    /// way.
+    pub strikethrough: bool,
+    /// Parse `![alt](src)` as an image rather than a literal `!` before a link.
+    pub images: bool,
+    /// Parse `` `code` `` as a code span rather than literal backticks.
+    pub inline_code: bool,
+    /// Parse GFM pipe tables.
+    pub tables: bool,
+    /// Parse `- [ ]` and `- [x]` items as tasks rather than plain list items.
+    pub task_lists: bool,
+    /// Turn bare `http://`, `https://` and `www.` urls and emails in the text into links.
+    pub autolinks: bool,
+    /// Deepest `#` header recognised, up to 6, deeper ones being plain paragraphs.
+    pub max_header_level: usize,
+    /// Join indented lines directly following a header into that header's text.
+    pub join_wrapped_headers: bool,
+    /// Let emphasis delimiters next to CJK characters open and close as they do next to spaces,
+    /// so that `这是**「粗体」**文字` is bold even though CJK text has no spaces between words.
+    pub cjk_emphasis: bool,
+    /// Report malformed input as an error from `MarkDown::try_parse_with` instead of making the
+    /// best of it. `MarkDown::parse_with` always makes the best of it.
+    pub strict: bool,
+    /// Unicode normalization form applied to the parsed text, code blocks excepted.
+    #[cfg(feature = "normalization")]
+    pub normalization: Option<Normalization>,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            strikethrough: true,
+            images: true,
+            inline_code: true,
+            tables: true,
+            task_lists: true,
+            autolinks: true,
+            max_header_level: 6,
+            join_wrapped_headers: false,
+            cjk_emphasis: false,
+            strict: false,
+            #[cfg(feature = "normalization")]
+            normalization: None,
+        }
+    }
+}
+
+#[cfg(feature = "normalization")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    Nfc,
+    Nfkc,
+}
@@ -1,4 +1,5 @@
-use std::fmt;
+use alloc::vec::Vec;
+use core::fmt;
 
 pub struct Queue<T> {
     buf: Vec<T>,
@@ -0,0 +1,477 @@
+use alloc::{borrow::Cow, format, string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    document::{
+        Alignment, CodeBlock, ImageSize, Inline, ListKind, Node, Style, Text, TextFragment,
+    },
+    render::Renderer,
+};
+
+use super::{entity_at, is_email, is_uri, MarkDown};
+
+// Canonical markdown, parsing back into an equivalent document
+impl<'a> fmt::Display for MarkDown<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.render(&mut MarkdownRenderer {
+            f,
+            empty: true,
+            line_prefix: String::new(),
+            line_start: true,
+            list_start: false,
+            loose_item: false,
+            after_table: false,
+        })
+    }
+}
+
+struct MarkdownRenderer<'r, W> {
+    f: &'r mut W,
+    empty: bool,
+    // Written after each newline of a text, so that its lines stay in their block
+    line_prefix: String,
+    line_start: bool,
+    // No item of the list just started has been written yet
+    list_start: bool,
+    // The item written last is loose, so a list nested in it starts after a blank line
+    loose_item: bool,
+    // A line after a table would be taken for one of its rows
+    after_table: bool,
+}
+
+impl<'r, W: fmt::Write> MarkdownRenderer<'r, W> {
+    // Blocks are separated rather than ended by a newline, which would add a blank line
+    fn block_start(&mut self) -> fmt::Result {
+        if !self.empty {
+            self.f.write_str("\n")?;
+        }
+        if self.after_table {
+            self.f.write_str("\n")?;
+            self.after_table = false;
+        }
+        self.empty = false;
+        Ok(())
+    }
+
+    fn block_text(&mut self, prefix: &str, line_prefix: String, text: &Text) -> fmt::Result {
+        self.block_start()?;
+        self.f.write_str(prefix)?;
+        self.line_prefix = line_prefix;
+        self.line_start = true;
+        self.text(text)
+    }
+
+    fn write_inlines(&mut self, inlines: &[Inline]) -> fmt::Result {
+        let mut inlines = inlines.iter().peekable();
+        while let Some(inline) = inlines.next() {
+            match inline {
+                // Escapes split a text in pieces, which are joined back to see where lines start
+                Inline::Text(s) => {
+                    let mut s = Cow::Borrowed(*s);
+                    while let Some(Inline::Text(next)) =
+                        inlines.next_if(|inline| matches!(inline, Inline::Text(_)))
+                    {
+                        s.to_mut().push_str(next);
+                    }
+                    // `!` right before a link would make an image of it
+                    match s.strip_suffix('!') {
+                        Some(s) if matches!(inlines.peek(), Some(Inline::Link(_, _, _))) => {
+                            self.write_escaped(s)?;
+                            self.f.write_str("\\!")?
+                        }
+                        _ => self.write_escaped(&s)?,
+                    }
+                }
+                Inline::Group(style, children) if *style == Style::Code => {
+                    let code = code_content(children);
+                    let fence = "`".repeat(longest_run(&code, '`') + 1);
+                    // Padding spaces are stripped back when parsed
+                    let padding = if code.starts_with('`')
+                        || code.ends_with('`')
+                        || (code.starts_with(' ')
+                            && code.ends_with(' ')
+                            && !code.trim_start_matches(' ').is_empty())
+                    {
+                        " "
+                    } else {
+                        ""
+                    };
+                    self.f
+                        .write_fmt(format_args!("{fence}{padding}{code}{padding}{fence}"))?;
+                }
+                Inline::Group(style, children) => {
+                    let delimiter = match *style {
+                        Style::Strong => "**",
+                        Style::Emphasis => "*",
+                        Style::Strikethrough => "~~",
+                        Style::Highlight => "==",
+                        Style::Sub => "~",
+                        Style::Sup => "^",
+                        _ => "",
+                    };
+                    self.f.write_str(delimiter)?;
+                    self.write_inlines(children)?;
+                    self.f.write_str(delimiter)?;
+                }
+                // Written back as `<url>` when its text is its url, as autolinks are
+                Inline::Link(alt, link, None) if is_autolink(alt, link) => {
+                    let url = link.strip_prefix("mailto:").unwrap_or(link);
+                    self.f.write_fmt(format_args!("<{url}>"))?;
+                }
+                Inline::Link(alt, link, title) => {
+                    self.f.write_str("[")?;
+                    self.write_inlines(alt)?;
+                    self.f.write_str("](")?;
+                    write_destination(link, ImageSize::default(), *title, self.f)?;
+                }
+                Inline::Image(alt, src, title, size) => {
+                    self.f.write_fmt(format_args!("![{alt}]("))?;
+                    write_destination(src, *size, *title, self.f)?;
+                }
+                Inline::FootnoteRef(number) => self.f.write_fmt(format_args!("[^{number}]"))?,
+                Inline::HardBreak => self.f.write_str("\\")?,
+            }
+            self.line_start = false;
+        }
+        Ok(())
+    }
+
+    fn write_escaped(&mut self, s: &str) -> fmt::Result {
+        for (idx, line) in s.split('\n').enumerate() {
+            if idx > 0 {
+                self.f.write_str("\n")?;
+                self.f.write_str(&self.line_prefix)?;
+                self.line_start = true;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            // What would start a block at the beginning of a line, spaces before it included
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let rest = &line[indent..];
+            let digits = rest.len() - rest.trim_start_matches(char::is_numeric).len();
+            let block_start = if rest.starts_with(['#', '>', '-', '+']) || rest.starts_with(":::") {
+                Some(indent)
+            } else if digits > 0 && rest[digits..].starts_with(['.', ')']) {
+                Some(indent + digits)
+            } else {
+                None
+            };
+
+            // Urls left as text would be linked once the text around them is escaped, so the
+            // character after their `http` or `www` is escaped to keep them as they are
+            let url_breaks: Vec<usize> = [("http://", 4), ("https://", 5), ("www.", 3)]
+                .into_iter()
+                .flat_map(|(start, at)| line.match_indices(start).map(move |(idx, _)| idx + at))
+                .collect();
+
+            for (idx, c) in line.char_indices() {
+                if ['\\', '*', '_', '`', '[', ']', '~', '=', '^', '<', '|'].contains(&c)
+                    || url_breaks.contains(&idx)
+                    || (self.line_start && block_start == Some(idx))
+                    || (c == '&' && entity_at(&line[idx..]).is_some())
+                    // An email would be linked once the text after it is escaped, as `a@b.c_`
+                    || (c == '@' && line[idx + 1..].starts_with(|c: char| c.is_alphanumeric()))
+                {
+                    self.f.write_str("\\")?;
+                }
+                self.f.write_char(c)?;
+            }
+            self.line_start = false;
+        }
+        Ok(())
+    }
+}
+
+impl<'r, W: fmt::Write> Renderer for MarkdownRenderer<'r, W> {
+    // A header written on several lines, or with spaces around it, only keeps them underlined
+    fn header(&mut self, level: usize, text: &Text) -> fmt::Result {
+        let plain_text = text.to_plain_text();
+        if level <= 2 && (plain_text.contains('\n') || plain_text.trim().len() < plain_text.len()) {
+            self.block_text("", String::new(), text)?;
+            let underline = if level == 1 { "===" } else { "---" };
+            return self.f.write_fmt(format_args!("\n{underline}"));
+        }
+        self.block_text(&format!("{} ", "#".repeat(level)), String::new(), text)?;
+        // Hashes ending the text after a space would be taken for closing ones, which are stripped
+        let content = plain_text.trim_end_matches('#');
+        if content.len() < plain_text.len()
+            && (content.is_empty() || content.ends_with(char::is_whitespace))
+        {
+            self.f.write_str(" #")?;
+        }
+        Ok(())
+    }
+
+    fn paragraph(&mut self, text: &Text) -> fmt::Result {
+        self.block_text("", String::new(), text)
+    }
+
+    fn quote_start(&mut self, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn quote(&mut self, deepth: usize, text: &Text) -> fmt::Result {
+        let prefix = format!("{} ", ">".repeat(deepth));
+        self.block_text(&prefix, prefix.clone(), text)
+    }
+
+    fn quote_end(&mut self, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn list_start(&mut self, kind: &ListKind, _deepth: usize, _items: &[Node]) -> fmt::Result {
+        self.list_start = true;
+        if kind.deepth() > 0 && self.loose_item {
+            self.f.write_str("\n")?;
+        }
+        Ok(())
+    }
+
+    fn list_item(
+        &mut self,
+        kind: &ListKind,
+        _deepth: usize,
+        paragraphs: &[Text],
+        loose: bool,
+    ) -> fmt::Result {
+        // Blank lines between its items are what make a list loose
+        if loose && !self.list_start {
+            self.f.write_str("\n")?;
+        }
+        self.list_start = false;
+        self.loose_item = loose;
+
+        let indent = "\t".repeat(kind.deepth());
+        let marker = match kind {
+            ListKind::Oredred(_, delimiter) => format!("1{delimiter} "),
+            // An item with an empty first line under a paragraph would be taken for a `---`
+            // underline
+            ListKind::Unordere(_)
+                if paragraphs.first().is_none_or(|text| {
+                    text.to_plain_text()
+                        .lines()
+                        .next()
+                        .is_none_or(|line| line.trim().is_empty())
+                }) =>
+            {
+                "* ".into()
+            }
+            ListKind::Unordere(_) => "- ".into(),
+            ListKind::Task(_, false) => "- [ ] ".into(),
+            ListKind::Task(_, true) => "- [x] ".into(),
+        };
+        // The following paragraphs are indented up to the text after `1. ` or `- `
+        let line_prefix = format!("{indent}{:1$}", "", marker.find(' ').unwrap_or(0) + 1);
+        for (idx, text) in paragraphs.iter().enumerate() {
+            if idx == 0 {
+                self.block_text(&format!("{indent}{marker}"), line_prefix.clone(), text)?;
+            } else {
+                self.f.write_str("\n")?;
+                self.block_text(&line_prefix, line_prefix.clone(), text)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn list_item_end(&mut self, _kind: &ListKind, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn list_end(&mut self, _kind: &ListKind, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        header: &[Text],
+        alignments: &[Option<Alignment>],
+        rows: &[Vec<Text>],
+    ) -> fmt::Result {
+        let delimiters: Vec<_> = alignments
+            .iter()
+            .map(|alignment| match alignment {
+                Some(Alignment::Left) => ":--",
+                Some(Alignment::Center) => ":-:",
+                Some(Alignment::Right) => "--:",
+                None => "---",
+            })
+            .collect();
+
+        for (idx, row) in core::iter::once(header)
+            .chain(rows.iter().map(Vec::as_slice))
+            .enumerate()
+        {
+            self.block_start()?;
+            for cell in row {
+                self.f.write_str("| ")?;
+                self.line_start = false;
+                self.text(cell)?;
+                self.f.write_str(" ")?;
+            }
+            self.f.write_str("|")?;
+            if idx == 0 {
+                self.f
+                    .write_fmt(format_args!("\n| {} |", delimiters.join(" | ")))?;
+            }
+        }
+        self.after_table = true;
+        Ok(())
+    }
+
+    fn code_block(&mut self, codeblock: &CodeBlock) -> fmt::Result {
+        let code = codeblock.fetch().unwrap_or_default();
+        // A fence is closed by the first line made of at least as many of its character
+        let fence_char = if codeblock.language.contains('`') || codeblock.meta.contains('`') {
+            '~'
+        } else {
+            '`'
+        };
+        let longest_fence = code
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && line.chars().all(|c| c == fence_char))
+            .map(str::len)
+            .max()
+            .unwrap_or_default();
+        let fence = String::from(fence_char).repeat(longest_fence.max(2) + 1);
+
+        self.block_start()?;
+        self.f.write_str(&fence)?;
+        self.f.write_str(&codeblock.language)?;
+        if !codeblock.meta.is_empty() {
+            self.f.write_fmt(format_args!(" {}", codeblock.meta))?;
+        }
+        if codeblock.line_start != 1 {
+            self.f
+                .write_fmt(format_args!(" {{start={}}}", codeblock.line_start))?;
+        }
+        self.f.write_str("\n")?;
+        self.f.write_str(code)?;
+        if !code.is_empty() && !code.ends_with('\n') {
+            self.f.write_str("\n")?;
+        }
+        self.f.write_str(&fence)
+    }
+
+    fn container_start(&mut self, name: &str, attrs: &[(Cow<str>, Cow<str>)]) -> fmt::Result {
+        self.block_start()?;
+        // A name starting with `:` would be taken for a longer fence
+        let space = if name.starts_with(':') { " " } else { "" };
+        self.f.write_fmt(format_args!(":::{space}{name}"))?;
+        for (attr, value) in attrs {
+            if value.is_empty() {
+                self.f.write_fmt(format_args!(" {attr}"))?;
+            } else if value.contains('"') {
+                self.f.write_fmt(format_args!(" {attr}='{value}'"))?;
+            } else {
+                self.f.write_fmt(format_args!(" {attr}=\"{value}\""))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn container_end(&mut self, _name: &str) -> fmt::Result {
+        self.block_start()?;
+        self.f.write_str(":::")
+    }
+
+    // A blank line, which needs a newline of its own when it starts the document
+    fn line_break(&mut self) -> fmt::Result {
+        self.after_table = false;
+        if self.empty {
+            self.f.write_str("\n")?;
+        }
+        self.block_start()
+    }
+
+    // `---` under a paragraph would make it a header
+    fn rule(&mut self) -> fmt::Result {
+        self.block_start()?;
+        self.f.write_str("***")
+    }
+
+    // Definitions are taken out of the text, so a blank line before them would be one more
+    fn footnotes_start(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn footnote(&mut self, number: usize, text: &Text) -> fmt::Result {
+        self.block_text(&format!("[^{number}]: "), String::new(), text)
+    }
+
+    fn footnotes_end(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn text_fragment(&mut self, fragment: &TextFragment) -> fmt::Result {
+        self.text(&Text {
+            content: vec![fragment.clone()],
+        })
+    }
+
+    // Styles span several fragments, so they are written from the whole text at once
+    fn text(&mut self, text: &Text) -> fmt::Result {
+        self.write_inlines(&text.inlines())
+    }
+}
+
+fn is_autolink(alt: &[Inline], link: &str) -> bool {
+    let [Inline::Text(text)] = alt else {
+        return false;
+    };
+    !text.contains('>')
+        && ((*text == link && is_uri(text))
+            || (link.strip_prefix("mailto:") == Some(*text) && is_email(text)))
+}
+
+fn code_content(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(s) => Cow::Borrowed(*s),
+            Inline::Group(_, children) => Cow::Owned(code_content(children)),
+            _ => Cow::Borrowed(""),
+        })
+        .collect()
+}
+
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or_default()
+}
+
+fn write_destination(
+    link: &str,
+    size: ImageSize,
+    title: Option<&str>,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    // Spaces only fit between `<>`, elsewhere backslashes and unbalanced parentheses are escaped
+    if link.contains(' ') && !link.contains(['<', '>']) {
+        f.write_fmt(format_args!("<{link}>"))?;
+    } else {
+        let balanced = link.chars().try_fold(0usize, |deepth, c| match c {
+            '(' => Some(deepth + 1),
+            ')' => deepth.checked_sub(1),
+            _ => Some(deepth),
+        }) == Some(0);
+        for (idx, c) in link.char_indices() {
+            if c == '\\' || (!balanced && ['(', ')'].contains(&c)) || (idx == 0 && c == '<') {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+    }
+    if !size.is_empty() {
+        f.write_fmt(format_args!(" {size}"))?;
+    }
+    match title {
+        Some(title) if title.contains('"') => f.write_fmt(format_args!(" '{title}')")),
+        Some(title) => f.write_fmt(format_args!(" \"{title}\")")),
+        None => f.write_str(")"),
+    }
+}
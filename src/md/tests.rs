@@ -0,0 +1,1340 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    document::{DocumentOwned, DocumentStats, Node, Style, TextFragment},
+    html::HTML,
+};
+
+use super::{entity::entity_at, MarkDown, MdError, ParserOptions};
+
+fn html(s: &str) -> String {
+    HTML::from(MarkDown::from(s)).to_string()
+}
+
+#[test]
+fn indented_code() {
+    assert_eq!(
+        html("    let a;\n\tlet b;"),
+        "<pre><code>let a;\nlet b;\n</code></pre>"
+    );
+    assert_eq!(html("a\n    b"), "<p>a\n    b</p>");
+}
+
+// A fenced block keeps a span of the source rather than a copy of its code
+#[test]
+fn code_block_spans() {
+    let source = "text\n```rust title=a\nfn main() {}\nlet a;\n```\n";
+    let md = MarkDown::from(source);
+    let Node::CodeBlock(codeblock) = &md.0.nodes[1] else {
+        panic!("{:?}", md.0.nodes)
+    };
+    let code = codeblock.fetch().unwrap();
+    assert_eq!(code, "fn main() {}\nlet a;\n");
+    assert_eq!(
+        &source[codeblock.code.offset..][..codeblock.code.length],
+        code
+    );
+    assert!(source.as_bytes().as_ptr_range().contains(&code.as_ptr()));
+    assert_eq!(
+        (&*codeblock.language, &*codeblock.meta),
+        ("rust", "title=a")
+    );
+
+    let owned = codeblock.clone().into_owned();
+    assert_eq!(owned.fetch(), Some(code));
+    assert_eq!(owned.code.offset, 0);
+}
+
+#[test]
+fn fence_lengths() {
+    for (s, expected) in [
+        ("~~~\n```\nx\n~~~", "<pre><code>```\nx\n</code></pre>"),
+        ("````\n```\nx\n````", "<pre><code>```\nx\n</code></pre>"),
+        ("```\nx\n`````", "<pre><code>x\n</code></pre>"),
+        (
+            "~~~~rust\nx\n~~~\n~~~~~",
+            "<pre><code class=\"language-rust\">x\n~~~\n</code></pre>",
+        ),
+        (
+            ":::note\n````\n```\n:::\n````\n:::",
+            "<div class=\"note\"><pre><code>```\n:::\n</code></pre></div>",
+        ),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+    assert_eq!(
+        MarkDown::from("````\n```\nx\n````").to_string(),
+        "````\n```\nx\n````"
+    );
+}
+
+#[test]
+fn info_strings() {
+    let info = |s| {
+        let md = MarkDown::from(s);
+        let Node::CodeBlock(codeblock) = &md.0.nodes[0] else {
+            panic!("{:?}", md.0.nodes)
+        };
+        (codeblock.language.to_string(), codeblock.meta.to_string())
+    };
+    assert_eq!(
+        info("```python title=foo\nx\n```"),
+        ("python".into(), "title=foo".into())
+    );
+    assert_eq!(
+        info("```  rust,ignore  \nx\n```"),
+        ("rust,ignore".into(), String::new())
+    );
+    assert_eq!(info("```\nx\n```"), (String::new(), String::new()));
+    assert_eq!(
+        info("```js  a  b  {start=3}\nx\n```"),
+        ("js".into(), "a  b".into())
+    );
+    assert_eq!(
+        MarkDown::from("```python title=foo {start=3}\nx\n```").to_string(),
+        "```python title=foo {start=3}\nx\n```"
+    );
+}
+
+#[test]
+fn blank_indented_lines_are_not_code() {
+    for s in ["\t", "    ", "\n\t", "\t\n\t\n"] {
+        assert!(!html(s).contains("<pre>"), "{s:?}");
+    }
+    assert_eq!(html("a\n\n    \nb"), "<p>a</p><br><p>b</p>");
+    assert_eq!(html("# h\n    \nfoo"), "<h1>h</h1><p>foo</p>");
+    assert_eq!(
+        html("    a\n\n\t\n    b\n    "),
+        "<pre><code>a\n\n\nb\n</code></pre><br>"
+    );
+}
+
+#[test]
+fn empty_list_items() {
+    assert_eq!(html("- "), "<ul><li></li></ul>");
+    assert_eq!(html("1. "), "<ol><li></li></ol>");
+    assert_eq!(html("-"), "<p>-</p>");
+    assert_eq!(
+        html("- a\n- \n- c"),
+        "<ul><li>a</li><li></li><li>c</li></ul>"
+    );
+    assert_eq!(html("1. \n2. b"), "<ol><li></li><li>b</li></ol>");
+    assert_eq!(html("- \n-"), "<ul><li></li></ul><p>-</p>");
+    assert_eq!(
+        html("- \n-\n1. "),
+        "<ul><li></li></ul><p>-</p><ol><li></li></ol>"
+    );
+    assert_eq!(html("- \n1. "), "<ul><li></li></ul><ol><li></li></ol>");
+    for s in ["- ", "-", "1. ", "- a\n- \n- c", "- \n-\n1. "] {
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn byte_order_mark() {
+    assert_eq!(html("\u{FEFF}# Title\ntext"), "<h1>Title</h1><p>text</p>");
+    assert_eq!(html("\u{FEFF}"), "");
+    assert_eq!(html("a \u{FEFF}b"), "<p>a \u{FEFF}b</p>");
+    assert_eq!(MarkDown::from("\u{FEFF}# Title").to_string(), "# Title");
+}
+
+#[test]
+fn mixed_line_endings() {
+    let unix = "a\nb  \nc\nd\n\n```\nx\ny\n```\n# h\n";
+    let mixed = "a\r\nb  \r\nc\rd\n\n```\r\nx\r\ny\n```\r\n# h\r";
+    assert_eq!(
+        html(mixed),
+        "<p>a\nb<br>\nc\nd</p><br><pre><code>x\ny\n</code></pre><h1>h</h1>"
+    );
+    assert_eq!(html(mixed), html(unix));
+    assert_eq!(
+        MarkDown::from(mixed).to_string(),
+        "a\nb\\\nc\nd\n\n```\nx\ny\n```\n# h"
+    );
+}
+
+#[test]
+fn continued_list_items() {
+    for (s, expected) in [
+        ("- a\n  b\n- c", "<ul><li>a\nb</li><li>c</li></ul>"),
+        ("- a\nlazy", "<ul><li>a\nlazy</li></ul>"),
+        (
+            "- a\n\n  b\n- c",
+            "<ul><li><p>a</p><p>b</p></li><li><p>c</p></li></ul>",
+        ),
+        (
+            "1. a\n\n   b\n   more",
+            "<ol><li><p>a</p><p>b\nmore</p></li></ol>",
+        ),
+        ("1. a\n\n  b", "<ol><li>a</li></ol><br><p>  b</p>"),
+        (
+            "- a\n    - b\n\n      c",
+            "<ul><li>a<ul><li><p>b</p><p>c</p></li></ul></li></ul>",
+        ),
+        ("- a\n\n  # h", "<ul><li>a</li></ul><br><h1>h</h1>"),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn loose_lists() {
+    for (s, expected) in [
+        ("- a\n- b", "<ul><li>a</li><li>b</li></ul>"),
+        ("- a\n\n- b", "<ul><li><p>a</p></li><li><p>b</p></li></ul>"),
+        (
+            "1. a\n\n\n2. b\n3. c",
+            "<ol><li><p>a</p></li><li><p>b</p></li><li><p>c</p></li></ol>",
+        ),
+        (
+            "- a\n    - b\n\n    - c\n- d",
+            "<ul><li>a<ul><li><p>b</p></li><li><p>c</p></li></ul></li><li>d</li></ul>",
+        ),
+        (
+            "- a\n- b\n\npara",
+            "<ul><li>a</li><li>b</li></ul><br><p>para</p>",
+        ),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn paren_delimiters() {
+    for (s, expected) in [
+        ("1) a\n2) b", "<ol><li>a</li><li>b</li></ol>"),
+        (
+            "1. a\n2) b\n3) c",
+            "<ol><li>a</li></ol><ol><li>b</li><li>c</li></ol>",
+        ),
+        (
+            "- a\n    1) b\n    2. c",
+            "<ul><li>a<ol><li>b</li></ol><ol><li>c</li></ol></li></ul>",
+        ),
+        ("1)a", "<p>1)a</p>"),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+    assert_eq!(
+        MarkDown::from("1) a\n2) b\n1. c").to_string(),
+        "1) a\n1) b\n1. c"
+    );
+    assert_round_trip("1\\) not a list");
+}
+
+#[test]
+fn list_nesting_jumps() {
+    assert_eq!(
+        html("- a\n        - b\n- c"),
+        "<ul><li>a<ul><li><ul><li>b</li></ul></li></ul></li><li>c</li></ul>"
+    );
+    assert_eq!(
+        html("1. a\n        1. b"),
+        "<ol><li>a<ol><li><ol><li>b</li></ol></li></ol></li></ol>"
+    );
+    assert_eq!(
+        html("- a\n    1. b\n    2. c\n- d"),
+        "<ul><li>a<ol><li>b</li><li>c</li></ol></li><li>d</li></ul>"
+    );
+    assert_eq!(
+        html("- a\n    - b\n        - c\n\ntext"),
+        "<ul><li>a<ul><li>b<ul><li>c</li></ul></li></ul></li></ul><br><p>text</p>"
+    );
+    for s in ["- a\n        - b\n- c", "- a\n    1. b\n    2. c\n- d"] {
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn tab_stops() {
+    assert_eq!(html("- x\n\t- a"), html("- x\n    - a"));
+    assert_eq!(html("- x\n  \t- a"), html("- x\n    - a"));
+    assert_eq!(html("- x\n\t\t- a"), html("- x\n        - a"));
+    assert_eq!(html("  \tcode"), html("    code"));
+    let narrow = ParserOptions {
+        tab_width: 2,
+        ..ParserOptions::default()
+    };
+    let html_with = |s| HTML::from(MarkDown::parse_with(s, &narrow)).to_string();
+    assert_eq!(html_with("- x\n\t- a"), html_with("- x\n  - a"));
+    assert_eq!(html_with("- x\n\t- a"), html("- x\n    - a"));
+}
+
+#[test]
+fn setext_headers() {
+    assert_eq!(html("Title\n====="), "<h1>Title</h1>");
+    assert_eq!(
+        html("Sub *title*\n---\ntext"),
+        "<h2>Sub <em>title</em></h2><p>text</p>"
+    );
+    assert_eq!(html("a\nb\n="), "<h1>a\nb</h1>");
+    assert_eq!(html("- item\n---"), "<ul><li>item</li></ul><hr>");
+    assert_eq!(html("text\n- item"), "<p>text</p><ul><li>item</li></ul>");
+    assert_eq!(html("# atx\n==="), "<h1>atx</h1><p>===</p>");
+}
+
+#[test]
+fn rules_and_setext_headers() {
+    for (s, expected) in [
+        ("- - -", "<hr>"),
+        ("***", "<hr>"),
+        ("_ _ _", "<hr>"),
+        ("-*-", "<p>-*-</p>"),
+        ("text\n---", "<h2>text</h2>"),
+        ("text\n- - -", "<p>text</p><hr>"),
+        ("a\n\n---", "<p>a</p><br><hr>"),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn task_markers() {
+    assert_eq!(
+        html("- [X] done"),
+        "<ul><li><input type=\"checkbox\" disabled checked> done</li></ul>"
+    );
+    assert_eq!(
+        html("-  [ ] spaced"),
+        "<ul><li><input type=\"checkbox\" disabled> spaced</li></ul>"
+    );
+    assert_eq!(
+        html("- buy [ ] milk [x]"),
+        "<ul><li>buy [ ] milk [x]</li></ul>"
+    );
+    assert_round_trip("- [X] done\n- [ ] b\n- buy [ ] milk [x]");
+}
+
+#[test]
+fn hard_breaks() {
+    assert_eq!(html("a  \nb\\\nc\nd"), "<p>a<br>\nb<br>\nc\nd</p>");
+    assert_eq!(html("a\\\\\nb"), "<p>a\\\nb</p>");
+    assert_eq!(html("a \nb"), "<p>a \nb</p>");
+    assert_eq!(html("end  \n\nnext"), "<p>end</p><br><p>next</p>");
+    assert_round_trip("a  \nb\\\nc\nd");
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(
+        html("\\*a\\* \\_ \\[ \\a \\é \\\\"),
+        "<p>*a* _ [ \\a \\é \\</p>"
+    );
+    assert_eq!(html("\\a*b*"), "<p>\\a<em>b</em></p>");
+    assert_eq!(html("a\\\nb"), "<p>a<br>\nb</p>");
+    assert_eq!(
+        html("> a\\\n> b\\"),
+        "<blockquote><p>a<br>\nb\\</p></blockquote>"
+    );
+    assert_round_trip("\\*a\\* \\_ \\[ \\a \\é \\\\");
+}
+
+#[test]
+fn entities() {
+    assert_eq!(
+        html("a &amp; b &copy; &#169; &#x1F600; &#X41;"),
+        "<p>a &amp; b © © 😀 A</p>"
+    );
+    assert_eq!(
+        html("&notanentity; & &#; &#xZZ; AT&T"),
+        "<p>&amp;notanentity; &amp; &amp;#; &amp;#xZZ; AT&amp;T</p>"
+    );
+    assert_eq!(
+        html("*&lt;b&gt;* `&amp;` \\&amp; &#0;"),
+        "<p><em>&lt;b&gt;</em> <code>&amp;amp;</code> &amp;amp; \u{fffd}</p>"
+    );
+    assert_eq!(
+        MarkDown::from("&amp;copy; AT&T").to_string(),
+        "\\&copy; AT&T"
+    );
+}
+
+#[test]
+fn entity_search_is_bounded() {
+    let line = "&".repeat(50_000) + ";";
+    assert_eq!(entity_at(&line), None);
+    assert_eq!(entity_at(&format!("&amp{};", "a".repeat(40))), None);
+    assert_eq!(html(&line), format!("<p>{};</p>", "&amp;".repeat(50_000)));
+}
+
+#[test]
+fn tables() {
+    assert_eq!(
+        html("| a | :b | c: | :d: |\n|---|:--|--:|:-:|\n| 1 | 2 | 3 | 4 |"),
+        "<table><thead><tr><th>a</th><th style=\"text-align: left\">:b</th>\
+         <th style=\"text-align: right\">c:</th><th style=\"text-align: center\">:d:</th></tr></thead>\
+         <tbody><tr><td>1</td><td style=\"text-align: left\">2</td>\
+         <td style=\"text-align: right\">3</td><td style=\"text-align: center\">4</td></tr></tbody></table>"
+    );
+    let expected = "<table><thead><tr><th>a</th><th>b</th></tr></thead>\
+                    <tbody><tr><td>1</td><td>2</td></tr></tbody></table>";
+    for s in [
+        "a | b\n--|--\n1 | 2",
+        "| a | b\n|--|--\n| 1 | 2",
+        "a | b |\n--|--|\n1 | 2 |",
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+    assert_eq!(
+        html("| a | b |\n|---|---|\n| 1 |\n| 1 | 2 | 3 |\nafter"),
+        "<table><thead><tr><th>a</th><th>b</th></tr></thead>\
+         <tbody><tr><td>1</td><td></td></tr><tr><td>1</td><td>2</td></tr></tbody></table><p>after</p>"
+    );
+    // Another block ends the table, pipes or not
+    let header = "<table><thead><tr><th>a</th></tr></thead></table>";
+    for (s, after) in [
+        ("| a |\n|---|\n## a | b", "<h2>a | b</h2>"),
+        ("| a |\n|---|\n- x | y", "<ul><li>x | y</li></ul>"),
+        (
+            "| a |\n|---|\n> q | r",
+            "<blockquote><p>q | r</p></blockquote>",
+        ),
+        (
+            "| a |\n|---|\n```x|\ncode\n```",
+            "<pre><code class=\"language-x|\">code\n</code></pre>",
+        ),
+    ] {
+        assert_eq!(html(s), format!("{header}{after}"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn table_escapes() {
+    let row = |s: &str| {
+        let table = html(&format!("| a | b |\n|---|---|\n{s}"));
+        table
+            .split_once("<tbody>")
+            .and_then(|(_, rows)| rows.strip_suffix("</tbody></table>"))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        row("| a \\| b | \\\\ |"),
+        "<tr><td>a | b</td><td>\\</td></tr>"
+    );
+    assert_eq!(row("\\|x | y\\\\|"), "<tr><td>|x</td><td>y\\</td></tr>");
+    assert_eq!(
+        row("| `x | y` | `a \\| b` |"),
+        "<tr><td><code>x | y</code></td><td><code>a | b</code></td></tr>"
+    );
+    assert_round_trip("| a | b |\n|---|---|\n| a \\| b | `c \\| d` \\\\ |");
+}
+
+#[test]
+fn quotes() {
+    assert_eq!(
+        html("> a\n> b\n\nafter"),
+        "<blockquote><p>a\nb</p></blockquote><br><p>after</p>"
+    );
+    assert_eq!(
+        html("> a\n> > b\n> c"),
+        "<blockquote><p>a</p><blockquote><p>b</p></blockquote><p>c</p></blockquote>"
+    );
+    assert_eq!(
+        html("> q\n# h"),
+        "<blockquote><p>q</p></blockquote><h1>h</h1>"
+    );
+}
+
+#[test]
+fn quote_inlines() {
+    assert_eq!(
+        html("> **bold** and [a link](url)"),
+        "<blockquote><p><strong>bold</strong> and <a href=\"url\">a link</a></p></blockquote>"
+    );
+    assert_eq!(
+        html("> *em* `> code` ~~s~~\n> > nested **b**"),
+        "<blockquote><p><em>em</em> <code>&gt; code</code> <s>s</s></p>\
+         <blockquote><p>nested <strong>b</strong></p></blockquote></blockquote>"
+    );
+    assert_round_trip("> **bold** and [a link](url) `code`\n>> *nested*");
+}
+
+#[test]
+fn header_levels() {
+    for level in 1..=6 {
+        let s = format!("{} title", "#".repeat(level));
+        assert_eq!(html(&s), format!("<h{level}>title</h{level}>"));
+        assert_round_trip(&s);
+    }
+    assert_eq!(html("####### title"), "<p>####### title</p>");
+    assert_eq!(html("#title"), "<p>#title</p>");
+    assert_eq!(html("#"), "<h1></h1>");
+    let deeper = ParserOptions {
+        max_header_level: 9,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        HTML::from(MarkDown::parse_with("####### x", &deeper)).to_string(),
+        "<p>####### x</p>"
+    );
+    let shallow = ParserOptions {
+        max_header_level: 3,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        HTML::from(MarkDown::parse_with("### a\n\n#### b", &shallow)).to_string(),
+        "<h3>a</h3><p>#### b</p>"
+    );
+}
+
+#[test]
+fn closing_hashes() {
+    for (s, expected) in [
+        ("## Title ##", "<h2>Title</h2>"),
+        ("## b #####   ", "<h2>b</h2>"),
+        ("# Title #notaclose", "<h1>Title #notaclose</h1>"),
+        ("# C#", "<h1>C#</h1>"),
+        ("# a \\#", "<h1>a #</h1>"),
+        ("### \\###", "<h3>###</h3>"),
+        ("###", "<h3></h3>"),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn link_titles() {
+    for (s, expected) in [
+        ("[a](u \"t\")", "<a href=\"u\" title=\"t\">a</a>"),
+        ("[a](u 't')", "<a href=\"u\" title=\"t\">a</a>"),
+        (
+            "![a](i.png \"x < y\")",
+            "<img src=\"i.png\" alt=\"a\" title=\"x &lt; y\">",
+        ),
+        ("[a](u)", "<a href=\"u\">a</a>"),
+        ("[a](<u v> \"t\")", "<a href=\"u v\" title=\"t\">a</a>"),
+        ("[a](u v) b", "[a](u v) b"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn link_destinations() {
+    for (s, expected) in [
+        (
+            "see [Rust](https://en.wikipedia.org/wiki/Rust_(programming_language)) *now*",
+            "<p>see <a href=\"https://en.wikipedia.org/wiki/Rust_(programming_language)\">Rust</a> <em>now</em></p>",
+        ),
+        ("[x](a(b(c)))", "<p><a href=\"a(b(c))\">x</a></p>"),
+        ("[x](a\\)b) c", "<p><a href=\"a)b\">x</a> c</p>"),
+        ("[x](<a b>) c", "<p><a href=\"a b\">x</a> c</p>"),
+        (
+            "![i](/a_(b).png \"t\")",
+            "<p><img src=\"/a_(b).png\" alt=\"i\" title=\"t\"></p>",
+        ),
+        ("[x](a(b)", "<p>[x](a(b)</p>"),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn autolinks() {
+    for (s, expected) in [
+        (
+            "see <https://a.com/_x_> now",
+            "see <a href=\"https://a.com/_x_\">https://a.com/_x_</a> now",
+        ),
+        (
+            "<me@mail.org>",
+            "<a href=\"mailto:me@mail.org\">me@mail.org</a>",
+        ),
+        (
+            "<foo bar> and <a> 1 < 2",
+            "&lt;foo bar&gt; and &lt;a&gt; 1 &lt; 2",
+        ),
+        ("`<http://x.y>`", "<code>&lt;http://x.y&gt;</code>"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn bare_autolinks() {
+    for (s, expected) in [
+        (
+            "(https://x.com).",
+            "(<a href=\"https://x.com\">https://x.com</a>).",
+        ),
+        (
+            "see https://en.wikipedia.org/wiki/A_(b)!",
+            "see <a href=\"https://en.wikipedia.org/wiki/A_(b)\">https://en.wikipedia.org/wiki/A_(b)</a>!",
+        ),
+        (
+            "www.a.com, mail me@x.org.",
+            "<a href=\"http://www.a.com\">www.a.com</a>, mail <a href=\"mailto:me@x.org\">me@x.org</a>.",
+        ),
+        (
+            "[https://x.com](https://y.com)",
+            "<a href=\"https://y.com\">https://x.com</a>",
+        ),
+        (
+            "xhttps://y.com http://localhost a@b",
+            "xhttps://y.com http://localhost a@b",
+        ),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+    }
+    let options = ParserOptions {
+        autolinks: false,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        HTML::from(MarkDown::parse_with("https://x.com", &options)).to_string(),
+        "<p>https://x.com</p>"
+    );
+}
+
+#[test]
+fn footnotes() {
+    let sup = |n| format!("<sup id=\"fnref-{n}\"><a href=\"#fn-{n}\">{n}</a></sup>");
+    assert_eq!(
+        html("A[^b] and B[^a] again[^b] and [^none].\n\n[^a]: First *def*\n[^b]: Second[^a]"),
+        format!(
+            "<p>A{} and B{} again{} and [^none].</p><br><section class=\"footnotes\"><ol>\
+             <li id=\"fn-1\">Second{} <a href=\"#fnref-1\">\u{21a9}</a></li>\
+             <li id=\"fn-2\">First <em>def</em> <a href=\"#fnref-2\">\u{21a9}</a></li></ol></section>",
+            sup(1),
+            sup(2),
+            sup(1),
+            sup(2)
+        )
+    );
+    assert_eq!(html("[^x]: unused\nText"), "<p>Text</p>");
+    assert_eq!(
+        html("x\n\n    - [^1]: note\n\ny[^2]\n\n[^2]: two"),
+        format!(
+            "<p>x</p><br><p>y{}</p><br><section class=\"footnotes\"><ol>\
+             <li id=\"fn-1\">two <a href=\"#fnref-1\">\u{21a9}</a></li></ol></section>",
+            sup(1)
+        )
+    );
+}
+
+#[test]
+fn reference_labels() {
+    assert_eq!(
+        html("[foo bar] [FOO\tbar][] [x][foo BAR] [nope]\n[Foo  Bar]: /u \"t\""),
+        "<p><a href=\"/u\" title=\"t\">foo bar</a> <a href=\"/u\" title=\"t\">FOO\tbar</a> \
+         <a href=\"/u\" title=\"t\">x</a> [nope]</p>"
+    );
+    let long = "a".repeat(1000);
+    assert_eq!(
+        html(&format!("[{long}]: /u\n\n[{long}]")),
+        format!("<p>[{long}]: /u</p><br><p>[{long}]</p>")
+    );
+}
+
+#[test]
+fn reference_links() {
+    assert_eq!(
+        html(
+            "[text][ID] and [Other  Id][] and ![img][id]\n\n\
+             [id]: https://a.com \"Title\"\n[other id]: <https://b.com>\n\n[missing][nope] [x]"
+        ),
+        "<p><a href=\"https://a.com\" title=\"Title\">text</a> and <a href=\"https://b.com\">Other  Id</a> \
+         and <img src=\"https://a.com\" alt=\"img\" title=\"Title\"></p><br><p>[missing][nope] [x]</p>"
+    );
+    assert_eq!(
+        html("```\n[a]: /x\n```\n[a]"),
+        "<pre><code>[a]: /x\n</code></pre><p>[a]</p>"
+    );
+}
+
+#[test]
+fn definitions_in_blocks() {
+    assert_eq!(
+        html("- item\n\n  [id]: /url\n\ntext [link][id]"),
+        "<ul><li>item</li></ul><br><p>text <a href=\"/url\">link</a></p>"
+    );
+    assert_eq!(
+        html("- a\n\n  [id]: /url\n\n  b\n\n[l][id]"),
+        "<ul><li><p>a</p><p>b</p></li></ul><br><p><a href=\"/url\">l</a></p>"
+    );
+    assert_eq!(
+        html("[q][id]\n\n> [id]: /quoted"),
+        "<p><a href=\"/quoted\">q</a></p><br>"
+    );
+}
+
+#[test]
+fn many_brackets() {
+    let line = "[".repeat(20_000) + "]";
+    assert_eq!(html(&line), format!("<p>{line}</p>"));
+    let line = "[^".repeat(2_000) + "]";
+    assert!(html(&line).starts_with("<p>[<sup>[</sup>[<sup>[</sup>"));
+    let links = "[x] ".repeat(20_000);
+    assert_eq!(
+        html(&format!("[x]: /u\n\n{links}"))
+            .matches("<a href=\"/u\">x</a>")
+            .count(),
+        20_000
+    );
+}
+
+#[test]
+fn style_groups() {
+    assert_eq!(
+        html("**a *b* c**"),
+        "<p><strong>a <em>b</em> c</strong></p>"
+    );
+    assert_eq!(
+        html("*a **b** c*"),
+        "<p><em>a <strong>b</strong> c</em></p>"
+    );
+    assert_eq!(
+        html("*a [b](/u) c* **[d](/v)**"),
+        "<p><em>a <a href=\"/u\">b</a> c</em> <strong><a href=\"/v\">d</a></strong></p>"
+    );
+    assert_eq!(
+        html("==a `b` ~~c *d*~~=="),
+        "<p><mark>a <code>b</code> <s>c <em>d</em></s></mark></p>"
+    );
+
+    // The groups are kept in the document, their delimiters as modifiers
+    let md = MarkDown::from("**a *b* c**");
+    let Node::Paragraphe(text) = &md.0.nodes[0] else {
+        panic!("{:?}", md.0.nodes)
+    };
+    let [TextFragment::Group(Style::Strong, strong)] = text.content.as_slice() else {
+        panic!("{text:?}")
+    };
+    assert!(matches!(
+        strong.content.as_slice(),
+        [
+            TextFragment::Stylised(Style::Modifier, _),
+            TextFragment::Stylised(Style::Normal, _),
+            TextFragment::Group(Style::Emphasis, _),
+            TextFragment::Stylised(Style::Normal, _),
+            TextFragment::Stylised(Style::Modifier, _),
+        ]
+    ));
+}
+
+#[test]
+fn code_in_emphasis() {
+    assert_eq!(
+        html("**bold `code`**"),
+        "<p><strong>bold <code>code</code></strong></p>"
+    );
+    assert_eq!(
+        html("*`a` b* **`*c*`**"),
+        "<p><em><code>a</code> b</em> <strong><code>*c*</code></strong></p>"
+    );
+    assert_round_trip("**bold `code`**");
+}
+
+#[test]
+fn emphasis_and_links() {
+    for (s, expected) in [
+        (
+            "*text [link](url) more*",
+            "<em>text <a href=\"url\">link</a> more</em>",
+        ),
+        ("[*a*](u)", "<a href=\"u\"><em>a</em></a>"),
+        ("*a*[l](u)", "<em>a</em><a href=\"u\">l</a>"),
+        (
+            "**x [*y*](u)**",
+            "<strong>x <a href=\"u\"><em>y</em></a></strong>",
+        ),
+        ("*a [b](c) d* e", "<em>a <a href=\"c\">b</a> d</em> e"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn tildes() {
+    assert_eq!(html("a ~~strike~~ b"), "<p>a <s>strike</s> b</p>");
+    assert_eq!(html("a ~single~ b"), "<p>a <sub>single</sub> b</p>");
+    assert_eq!(html("a ~~~three~~~ b"), "<p>a ~~~three~~~ b</p>");
+    assert_eq!(
+        html("~~~\ncode ~~x~~\n~~~"),
+        "<pre><code>code ~~x~~\n</code></pre>"
+    );
+    assert_eq!(
+        html("~~~rust\n```\n~~~"),
+        "<pre><code class=\"language-rust\">```\n</code></pre>"
+    );
+    for s in ["a ~~strike~~ b", "a ~~~three~~~ b", "~~~\ncode ~~x~~\n~~~"] {
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn code_spans_are_literal() {
+    for (s, expected) in [
+        (
+            "`*x*` and `~~y~~`",
+            "<code>*x*</code> and <code>~~y~~</code>",
+        ),
+        ("`[l](u)` *e*", "<code>[l](u)</code> <em>e</em>"),
+        ("*a `b* c`", "*a <code>b* c</code>"),
+        ("``a ` b``", "<code>a ` b</code>"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn intraword_underscores() {
+    for (s, expected) in [
+        ("foo_bar_baz", "foo_bar_baz"),
+        (
+            "snake_case_name and _em_",
+            "snake_case_name and <em>em</em>",
+        ),
+        ("_foo_bar_", "<em>foo_bar</em>"),
+        ("foo*bar*baz", "foo<em>bar</em>baz"),
+        ("__a__", "<strong>a</strong>"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn highlights() {
+    for (s, expected) in [
+        ("a ==b== c", "a <mark>b</mark> c"),
+        ("a =b= c", "a =b= c"),
+        ("x == y", "x == y"),
+        ("**==x==**", "<strong><mark>x</mark></strong>"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+    assert_eq!(MarkDown::from("a ==b== = c").to_string(), "a ==b== \\= c");
+}
+
+#[test]
+fn sub_and_superscripts() {
+    for (s, expected) in [
+        ("H~2~O and 2^10^", "H<sub>2</sub>O and 2<sup>10</sup>"),
+        ("~~a~~", "<s>a</s>"),
+        ("a~b c~ d^e f^", "a~b c~ d^e f^"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+    let options = ParserOptions {
+        strikethrough: false,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        HTML::from(MarkDown::parse_with("~~a~~ ~b~", &options)).to_string(),
+        "<p>~~a~~ <sub>b</sub></p>"
+    );
+}
+
+#[test]
+fn code_span_runs() {
+    for (s, expected) in [
+        ("``code with ` inside``", "<code>code with ` inside</code>"),
+        ("`` `a` ``", "<code>`a`</code>"),
+        ("` a `", "<code>a</code>"),
+        ("`  a  `", "<code> a </code>"),
+        ("` `", "<code> </code>"),
+        ("````a``b````", "<code>a``b</code>"),
+        ("``a` b", "``a` b"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+// From the emphasis examples of the CommonMark spec
+#[test]
+fn nested_emphasis() {
+    for (s, expected) in [
+        ("*foo *bar* baz*", "<em>foo <em>bar</em> baz</em>"),
+        ("_foo _bar_ baz_", "<em>foo <em>bar</em> baz</em>"),
+        ("*(*foo*)*", "<em>(<em>foo</em>)</em>"),
+        (
+            "**foo **bar** baz**",
+            "<strong>foo <strong>bar</strong> baz</strong>",
+        ),
+        (
+            "__foo, __bar__, baz__",
+            "<strong>foo, <strong>bar</strong>, baz</strong>",
+        ),
+        ("*(**foo**)*", "<em>(<strong>foo</strong>)</em>"),
+        ("**foo*bar*baz**", "<strong>foo<em>bar</em>baz</strong>"),
+        ("*foo**bar**baz*", "<em>foo<strong>bar</strong>baz</em>"),
+        ("_foo __bar__ baz_", "<em>foo <strong>bar</strong> baz</em>"),
+        ("*foo [bar](/url)*", "<em>foo <a href=\"/url\">bar</a></em>"),
+        ("*foo _bar* baz_", "<em>foo _bar</em> baz_"),
+        ("**(**foo)", "**(**foo)"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn flanking_delimiters() {
+    for (s, expected) in [
+        ("a * b * c", "a * b * c"),
+        ("*a *", "*a *"),
+        ("x * a*", "x * a*"),
+        ("**a** b **c**", "<strong>a</strong> b <strong>c</strong>"),
+        ("~~a~~ ~~ b~~", "<s>a</s> ~~ b~~"),
+        ("*(a)*", "<em>(a)</em>"),
+    ] {
+        assert_eq!(html(s), format!("<p>{expected}</p>"), "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn cjk_emphasis() {
+    let cjk = ParserOptions {
+        cjk_emphasis: true,
+        ..ParserOptions::default()
+    };
+    let html_cjk = |s| HTML::from(MarkDown::parse_with(s, &cjk)).to_string();
+
+    assert_eq!(html("这是**「粗体」**文字"), "<p>这是**「粗体」**文字</p>");
+    assert_eq!(
+        html_cjk("这是**「粗体」**文字"),
+        "<p>这是<strong>「粗体」</strong>文字</p>"
+    );
+    assert_eq!(
+        html_cjk("日本語の*「強調」*です。**太字。**と"),
+        "<p>日本語の<em>「強調」</em>です。<strong>太字。</strong>と</p>"
+    );
+    // Unspaced CJK text without punctuation inside the run needs no relaxing
+    assert_eq!(
+        html("中文*强调*和**粗体**混合"),
+        "<p>中文<em>强调</em>和<strong>粗体</strong>混合</p>"
+    );
+    // Other scripts keep the strict rules
+    assert_eq!(html_cjk("a**\"b\"**c"), "<p>a**&quot;b&quot;**c</p>");
+}
+
+// Offsets are in bytes, so styles and links after multi-byte characters land where they are
+#[test]
+fn multibyte_offsets() {
+    for (s, expected) in [
+        (
+            "é **b** 😀 [l](/u) ü",
+            "<p>é <strong>b</strong> 😀 <a href=\"/u\">l</a> ü</p>",
+        ),
+        ("àé*x*ô", "<p>àé<em>x</em>ô</p>"),
+        (
+            "😀\\*a\\* &amp; 😀 `c`é",
+            "<p>😀*a* &amp; 😀 <code>c</code>é</p>",
+        ),
+        (
+            "ñ~~s~~ ^ü^ ==😀==",
+            "<p>ñ<s>s</s> <sup>ü</sup> <mark>😀</mark></p>",
+        ),
+        (
+            "😀 ![ï](/i.png) https://a.com/x é",
+            "<p>😀 <img src=\"/i.png\" alt=\"ï\"> <a href=\"https://a.com/x\">https://a.com/x</a> é</p>",
+        ),
+        (
+            "| é | 😀 |\n|---|---|\n| *à* | [ü](/u) |",
+            "<table><thead><tr><th>é</th><th>😀</th></tr></thead><tbody><tr><td><em>à</em></td><td><a href=\"/u\">ü</a></td></tr></tbody></table>",
+        ),
+    ] {
+        assert_eq!(html(s), expected, "{s:?}");
+        assert_round_trip(s);
+    }
+}
+
+#[test]
+fn stats() {
+    let md = MarkDown::from(
+        "# T\n## a [l](/l)\n## b\n\np ![i](/i) *[m](/m)*\n\n- x\n- ![j](/j)\n\n```\nc\n```\n\n> q [n](/n)\n\n:::note\n#### d\nr\n:::\n\n| h |\n|---|\n| [o](/o) |",
+    );
+    assert_eq!(
+        md.0.stats(),
+        DocumentStats {
+            headers: [1, 2, 0, 1, 0, 0],
+            paragraphs: 2,
+            list_items: 2,
+            code_blocks: 1,
+            links: 4,
+            images: 2,
+        }
+    );
+    assert_eq!(MarkDown::from("").0.stats(), DocumentStats::default());
+}
+
+#[test]
+fn strict_errors() {
+    let strict = ParserOptions {
+        strict: true,
+        ..ParserOptions::default()
+    };
+    let err = MarkDown::try_parse_with("a\n\n| a | b |\n|---|---|\n| 1 |\n", &strict).err();
+    assert_eq!(
+        err,
+        Some(MdError::UnevenTableRow {
+            line: 5,
+            cells: 1,
+            columns: 2
+        })
+    );
+    let err = MarkDown::try_parse_with("x\n\nsee [a][Nope] and [b]\n", &strict).err();
+    assert_eq!(
+        err,
+        Some(MdError::InvalidReference {
+            line: 3,
+            label: "Nope".into()
+        })
+    );
+    let err = MarkDown::try_parse("```rust\nfn main() {}").err();
+    assert_eq!(err, Some(MdError::UnterminatedCodeBlock { line: 1 }));
+    assert!(MarkDown::try_parse_with("see [a][x] and [b][]\n\n[x]: /u\n", &strict).is_ok());
+    assert!(MarkDown::try_parse_with("see [a][Nope]\n", &ParserOptions::default()).is_ok());
+}
+
+#[test]
+fn input_size_limit() {
+    assert!(MarkDown::parse_limited("# hi", 4).is_ok());
+    let err = MarkDown::parse_limited("# hi!", 4).err();
+    assert_eq!(err, Some(MdError::InputTooLarge { size: 5, limit: 4 }));
+    assert_eq!(
+        err.unwrap().to_string(),
+        "input of 5 bytes exceeds the limit of 4 bytes"
+    );
+}
+
+#[test]
+fn unterminated_fences() {
+    assert_eq!(
+        html("text\n```rust\nlet x = 1;\nlet y = 2;"),
+        "<p>text</p><pre><code class=\"language-rust\">let x = 1;\nlet y = 2;\n</code></pre>"
+    );
+    assert_eq!(html("```"), "<pre><code></code></pre>");
+    let strict = ParserOptions {
+        strict: true,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        MarkDown::try_parse_with("a\n:::note\nb\n```\ncode\n:::", &strict).err(),
+        Some(MdError::UnterminatedCodeBlock { line: 4 })
+    );
+    assert_eq!(
+        HTML::from(MarkDown::parse_with("```\nx", &strict)).to_string(),
+        "<pre><code>x\n</code></pre>"
+    );
+}
+
+#[test]
+fn from_str_is_lenient() {
+    assert!("```x".parse::<MarkDown>().is_ok());
+    assert!("| a |\n|---|\n| 1 | 2 |".parse::<MarkDown>().is_ok());
+    assert!("see [a][Nope]".parse::<MarkDown>().is_ok());
+    let markdown = "| a | b |\n|---|---|\n| 1 |".parse::<MarkDown>().unwrap();
+    assert_eq!(
+        HTML::from(markdown).to_string(),
+        html("| a | b |\n|---|---|\n| 1 |")
+    );
+}
+
+#[test]
+fn disabled_inline_features() {
+    let options = ParserOptions {
+        images: false,
+        inline_code: false,
+        strikethrough: false,
+        ..ParserOptions::default()
+    };
+    let html_with = |s| HTML::from(MarkDown::parse_with(s, &options)).to_string();
+    assert_eq!(
+        html_with("a ~~b~~ ![i](/x) `c` *d* ``e``"),
+        "<p>a ~~b~~ ![i](/x) `c` <em>d</em> ``e``</p>"
+    );
+    assert_eq!(
+        html_with("![*i*](/x \"t\") [l](/y)"),
+        "<p>![*i*](/x &quot;t&quot;) <a href=\"/y\">l</a></p>"
+    );
+    assert_eq!(html_with("`*e*`"), "<p>`<em>e</em>`</p>");
+    assert_eq!(
+        html("![i](/x) `*c*`"),
+        "<p><img src=\"/x\" alt=\"i\"> <code>*c*</code></p>"
+    );
+}
+
+#[test]
+fn disabled_block_features() {
+    let options = ParserOptions {
+        tables: false,
+        task_lists: false,
+        ..ParserOptions::default()
+    };
+    let html_with = |s| HTML::from(MarkDown::parse_with(s, &options)).to_string();
+    assert_eq!(html_with("a | b\n--|--"), "<p>a | b\n--|--</p>");
+    assert_eq!(html_with("- [x] a"), "<ul><li>[x] a</li></ul>");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn from_reader() {
+    let source = "# T\r\n\nsee [a][x]\n\n[x]: /u\n";
+    let markdown = MarkDown::from_reader(source.as_bytes()).unwrap();
+    assert_eq!(HTML::from(markdown).to_string(), html(source));
+    // Lines running over several fills of the buffer
+    let reader = std::io::BufReader::with_capacity(3, source.as_bytes());
+    let markdown = MarkDown::from_reader(reader).unwrap();
+    assert_eq!(HTML::from(markdown).to_string(), html(source));
+
+    let err = MarkDown::from_reader(&b"a\xff\n"[..]).err().unwrap();
+    assert!(matches!(
+        err,
+        MdError::Io {
+            kind: std::io::ErrorKind::InvalidData,
+            ..
+        }
+    ));
+    assert!(err.to_string().starts_with("failed to read the input"));
+}
+
+#[test]
+fn wrapped_headers() {
+    let source = "# A very long\n    wrapped *title*\nbody";
+    let joined = ParserOptions {
+        join_wrapped_headers: true,
+        ..ParserOptions::default()
+    };
+    assert_eq!(
+        HTML::from(MarkDown::parse_with(source, &joined)).to_string(),
+        "<h1>A very long wrapped <em>title</em></h1><p>body</p>"
+    );
+    assert_eq!(
+        html(source),
+        "<h1>A very long</h1><pre><code>wrapped *title*\n</code></pre><p>body</p>"
+    );
+}
+
+#[test]
+fn owned_documents() {
+    let expected = html("# Hi *there*\n```rs\ncode\n```\n:::note x=1\n[l](u)\n:::");
+    let owned: DocumentOwned = {
+        let source = String::from("# Hi *there*\n```rs\ncode\n```\n:::note x=1\n[l](u)\n:::");
+        MarkDown::from(source.as_str()).0.into_owned()
+    };
+    assert_eq!(HTML::from(MarkDown(owned)).to_string(), expected);
+    let owned: DocumentOwned = {
+        let source = String::from("para");
+        MarkDown::from(source.as_str()).0.to_owned()
+    };
+    assert_eq!(HTML::from(MarkDown(owned)).to_string(), "<p>para</p>");
+}
+
+#[test]
+fn ignored_regions() {
+    assert_eq!(
+        html("a\n<!-- htmd:ignore-start -->\n# hidden\n- x\n<!-- htmd:ignore-end -->\nb"),
+        "<p>a\nb</p>"
+    );
+    assert_eq!(
+        html("a\n\n<!--htmd:ignore-start-->\nhidden\nmore"),
+        "<p>a</p><br>"
+    );
+    assert_eq!(
+        html("```\n<!-- htmd:ignore-start -->\n```\nshown"),
+        "<pre><code>&lt;!-- htmd:ignore-start --&gt;\n</code></pre><p>shown</p>"
+    );
+}
+
+#[cfg(feature = "normalization")]
+#[test]
+fn normalization() {
+    use super::Normalization;
+
+    let normalized = |s, normalization| {
+        let options = ParserOptions {
+            normalization: Some(normalization),
+            ..ParserOptions::default()
+        };
+        HTML::from(MarkDown::parse_with(s, &options)).to_string()
+    };
+    let source = "Cafe\u{301} *cafe\u{301}*\n\n```\ne\u{301}\n```";
+    assert_eq!(
+        normalized(source, Normalization::Nfc),
+        "<p>Caf\u{e9} <em>caf\u{e9}</em></p><br><pre><code>e\u{301}\n</code></pre>"
+    );
+    assert_eq!(
+        html(source),
+        "<p>Cafe\u{301} <em>cafe\u{301}</em></p><br><pre><code>e\u{301}\n</code></pre>"
+    );
+    assert_eq!(normalized("\u{fb01}", Normalization::Nfkc), "<p>fi</p>");
+}
+
+#[test]
+fn retain_nodes() {
+    let mut md = MarkDown::from("before\n```\ncode\n```\nafter\n\n# Title\n```\nx\n```");
+    md.0.retain(|node| !matches!(node, Node::CodeBlock(_)));
+    assert_eq!(
+        HTML::from(md).to_string(),
+        "<p>before</p><br><p>after</p><br><h1>Title</h1>"
+    );
+    let mut md = MarkDown::from(":::note\na\n```\nc\n```\n:::");
+    md.0.retain(|node| !matches!(node, Node::CodeBlock(_)));
+    assert_eq!(md.0.stats().code_blocks, 0);
+    assert!(md.0.lines.is_empty());
+    assert_eq!(
+        HTML::from(md).to_string(),
+        "<div class=\"note\"><p>a</p></div>"
+    );
+}
+
+#[test]
+fn links_and_images() {
+    let md = MarkDown::from(
+        "[a](u1) ![i](p.png)\n:::x\n[r][d] and https://x.com\n:::\n| ![j](q.png) |\n|--|\n\n[d]: u2",
+    );
+    let links: Vec<_> =
+        md.0.links()
+            .into_iter()
+            .map(|(alt, url)| (alt.to_plain_text(), url))
+            .collect();
+    assert_eq!(
+        links,
+        [
+            ("a".into(), "u1"),
+            ("r".into(), "u2"),
+            ("https://x.com".into(), "https://x.com")
+        ]
+    );
+    assert_eq!(md.0.images(), [("i", "p.png"), ("j", "q.png")]);
+}
+
+#[test]
+fn node_lines() {
+    let md = MarkDown::from(
+        "# T\n\npara\ngraph\n\n- a\n  lazy\n- b\n\n```rs\nx\n```\nSetext\n---\n| a |\n|---|\n| 1 |",
+    );
+    assert_eq!(
+        md.0.lines,
+        [1..2, 3..5, 5..6, 6..8, 8..9, 9..10, 10..13, 13..15, 15..18]
+    );
+    assert!(matches!(md.0.nodes[7], Node::Header(2, _)));
+    assert!(MarkDown::from("").0.lines.is_empty());
+}
+
+// Parsing what a document is written back as gives the same document again
+fn assert_round_trip(s: &str) {
+    let written = MarkDown::from(s).to_string();
+    assert_eq!(html(&written), html(s), "{s:?} written as {written:?}");
+}
+
+#[test]
+fn round_trip() {
+    for s in [
+        "",
+        "[x]: /u",
+        "\n\na",
+        "a\\\n\nb",
+        "\\",
+        "a  \n\\",
+        "```\nx",
+        ":::  \n|",
+        ":::note\n\n:::",
+        "::: :x",
+        "!http://a.com",
+        "!www.b.org",
+        "http://a.com)http://a.com---",
+        "<http://a.com>",
+        "x@y.com_",
+        "http://a.com\\:b",
+        "[www.b.org:`](http://www.b.org:`)",
+        "[x](/a\\)b) [y](/a_(b)) [z](</a b>)",
+        "a\n[^1]\n[^1]: f",
+        "a  \nb\n===",
+        " a \n---",
+        " \\> a",
+        " \\# a",
+        "text\n* ",
+        "text\n\t* ",
+        "text\n*  \n^x",
+        "text\n* \n* ",
+        "| a |\n|---|\n| 1 |\n[x]: /u\nb | c",
+        "| a |\n|---|\n| 1 |\n\npara",
+        "1. ===\n\n        - ",
+        "- a\n\n    - b",
+    ] {
+        assert_round_trip(s);
+    }
+
+    assert_eq!(
+        MarkDown::from("see http://a.com and x@y.com").to_string(),
+        "see <http://a.com> and <x@y.com>"
+    );
+    assert_eq!(html("a\\"), "<p>a\\</p>");
+    assert_eq!(html("a\\\nb\\\n\nc"), "<p>a<br>\nb\\</p><br><p>c</p>");
+    assert_eq!(html("```\nx"), "<pre><code>x\n</code></pre>");
+}
+
+#[test]
+fn round_trip_combinations() {
+    let atoms = [
+        "a",
+        " ",
+        "\n",
+        "\n\n",
+        "*",
+        "**",
+        "_",
+        "`",
+        "[",
+        "]",
+        "(",
+        ")",
+        "<",
+        ">",
+        "!",
+        "\\",
+        "#",
+        "- ",
+        "1. ",
+        "> ",
+        "~~",
+        "=",
+        "^",
+        "&amp;",
+        "&",
+        "http://a.com",
+        "www.b.org",
+        "x@y.com",
+        "  \n",
+        "|",
+        "---",
+        "```",
+        ":::",
+        "[x]: /u",
+        "[^1]",
+        "[^1]: f",
+        "é",
+    ];
+    for a in atoms {
+        for b in atoms {
+            for c in atoms {
+                assert_round_trip(&format!("{a}{b}{c}"));
+            }
+        }
+    }
+}
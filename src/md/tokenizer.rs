@@ -0,0 +1,438 @@
+use alloc::{borrow::Cow, collections::VecDeque, vec, vec::Vec};
+
+use crate::document::{Alignment, Document, ImageSize, ListKind, Node, Style, Text, TextFragment};
+
+use super::MarkDown;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    HeaderStart(usize),
+    HeaderEnd(usize),
+    ParagraphStart,
+    ParagraphEnd,
+    ListStart(ListKind),
+    ListEnd(ListKind),
+    ListItemStart(ListKind),
+    ListItemEnd,
+    QuoteStart(usize), // deepth
+    QuoteEnd(usize),
+    TableStart,
+    TableEnd,
+    TableRowStart,
+    TableRowEnd,
+    TableCellStart {
+        header: bool,
+        alignment: Option<Alignment>,
+    },
+    TableCellEnd,
+    FenceOpen(Cow<'a, str>), // language
+    Code(Cow<'a, str>),
+    FenceClose,
+    ContainerStart(Cow<'a, str>),
+    ContainerEnd(Cow<'a, str>),
+    StyleOpen(Style),
+    StyleClose(Style),
+    Text(Cow<'a, str>),
+    LinkStart(Cow<'a, str>, Option<Cow<'a, str>>), // link, title
+    LinkEnd,
+    Image {
+        alt: Cow<'a, str>,
+        src: Cow<'a, str>,
+        title: Option<Cow<'a, str>>,
+        size: ImageSize,
+    },
+    LineBreak,
+    HardBreak,
+    Rule,
+    FootnoteRef(usize),
+    FootnoteStart(usize),
+    FootnoteEnd,
+}
+
+// Outermost first, so that the styles nest as the HTML tags do
+const STYLES: [Style; 7] = [
+    Style::Strikethrough,
+    Style::Highlight,
+    Style::Sub,
+    Style::Sup,
+    Style::Code,
+    Style::Emphasis,
+    Style::Strong,
+];
+
+/// The tokens of a parsed document, yielded node after node for building custom renderers. The
+/// whole text is parsed first, the tokens being taken from the document rather than the text.
+///
+/// Starts and ends are balanced, the lists nested in an item coming before its end.
+pub struct Tokens<'a> {
+    levels: Vec<Level<'a>>,
+    pending: VecDeque<Token<'a>>,
+}
+
+// The nodes left in the document or in a container, along the lists open among them and the token
+// closing the container
+struct Level<'a> {
+    nodes: vec::IntoIter<Node<'a>>,
+    lists: Vec<ListKind>,
+    end: Option<Token<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Self::from(MarkDown::from(s).0)
+    }
+
+    fn push_node(&mut self, node: Node<'a>) {
+        if !matches!(node, Node::List(_, _, _)) {
+            self.end_lists(0);
+        }
+
+        match node {
+            Node::Header(level, text) => {
+                self.pending.push_back(Token::HeaderStart(level));
+                self.push_text(text);
+                self.pending.push_back(Token::HeaderEnd(level));
+            }
+            Node::Paragraphe(text) => {
+                self.pending.push_back(Token::ParagraphStart);
+                self.push_text(text);
+                self.pending.push_back(Token::ParagraphEnd);
+            }
+            Node::List(list_kind, mut paragraphs, loose) => {
+                self.start_item(list_kind);
+                self.pending.push_back(Token::ListItemStart(list_kind));
+                // Items of loose lists have each of their paragraphs within its own tokens
+                if paragraphs.len() == 1 && !loose {
+                    self.push_text(paragraphs.remove(0));
+                } else {
+                    for text in paragraphs {
+                        self.pending.push_back(Token::ParagraphStart);
+                        self.push_text(text);
+                        self.pending.push_back(Token::ParagraphEnd);
+                    }
+                }
+            }
+            Node::Quote(deepth, text) => {
+                self.pending.push_back(Token::QuoteStart(deepth));
+                self.push_text(text);
+                self.pending.push_back(Token::QuoteEnd(deepth));
+            }
+            Node::Table {
+                header,
+                alignments,
+                rows,
+            } => {
+                self.pending.push_back(Token::TableStart);
+                self.push_table_row(header, &alignments, true);
+                for row in rows {
+                    self.push_table_row(row, &alignments, false);
+                }
+                self.pending.push_back(Token::TableEnd);
+            }
+            Node::CodeBlock(codeblock) => {
+                self.pending
+                    .push_back(Token::FenceOpen(codeblock.language.clone()));
+                self.pending.push_back(Token::Code(codeblock.into_code()));
+                self.pending.push_back(Token::FenceClose);
+            }
+            Node::Container { name, children, .. } => {
+                self.pending.push_back(Token::ContainerStart(name.clone()));
+                self.levels.push(Level {
+                    nodes: children.into_iter(),
+                    lists: Vec::new(),
+                    end: Some(Token::ContainerEnd(name)),
+                });
+            }
+            Node::LineBreak => self.pending.push_back(Token::LineBreak),
+            Node::Rule => self.pending.push_back(Token::Rule),
+            Node::Footnote(number, text) => {
+                self.pending.push_back(Token::FootnoteStart(number));
+                self.push_text(text);
+                self.pending.push_back(Token::FootnoteEnd);
+            }
+        }
+    }
+
+    // Ends the item above or opens the lists the item goes in, as `Document::render` does
+    fn start_item(&mut self, kind: ListKind) {
+        let deepth = kind.deepth();
+        let lists = &self.levels.last().unwrap().lists;
+        let kept = lists
+            .iter()
+            .take_while(|pre_list| pre_list.deepth() < deepth)
+            .count();
+        let kept = kept
+            + usize::from(
+                lists
+                    .get(kept)
+                    .is_some_and(|pre_list| kind.continues(pre_list)),
+            );
+        self.end_lists(kept);
+
+        let lists = &mut self.levels.last_mut().unwrap().lists;
+        match lists.last() {
+            Some(pre_list) if pre_list.deepth() == deepth => {
+                self.pending.push_back(Token::ListItemEnd)
+            }
+            // An item deeper by several levels sits in empty items of lists of its own kind
+            pre_list => {
+                let first_deepth = pre_list.map_or(deepth, |pre_list| pre_list.deepth() + 1);
+                for list_deepth in first_deepth..deepth {
+                    let list = kind.with_deepth(list_deepth);
+                    self.pending.push_back(Token::ListStart(list));
+                    self.pending.push_back(Token::ListItemStart(list));
+                    lists.push(list);
+                }
+                self.pending.push_back(Token::ListStart(kind));
+                lists.push(kind);
+            }
+        }
+    }
+
+    // Ends the lists nested past the `kept` first ones, along the items they are in
+    fn end_lists(&mut self, kept: usize) {
+        let lists = &mut self.levels.last_mut().unwrap().lists;
+        while lists.len() > kept {
+            let list = lists.pop().unwrap();
+            self.pending.push_back(Token::ListItemEnd);
+            self.pending.push_back(Token::ListEnd(list));
+        }
+    }
+
+    fn push_table_row(
+        &mut self,
+        row: Vec<Text<'a>>,
+        alignments: &[Option<Alignment>],
+        header: bool,
+    ) {
+        self.pending.push_back(Token::TableRowStart);
+        for (cell, alignment) in row.into_iter().zip(alignments) {
+            self.pending.push_back(Token::TableCellStart {
+                header,
+                alignment: *alignment,
+            });
+            self.push_text(cell);
+            self.pending.push_back(Token::TableCellEnd);
+        }
+        self.pending.push_back(Token::TableRowEnd);
+    }
+
+    fn push_text(&mut self, text: Text<'a>) {
+        let mut opened = Vec::new();
+
+        for frag in text.content {
+            match frag {
+                TextFragment::Stylised(style, s)
+                    if style.contains(Style::Modifier) || s.is_empty() =>
+                {
+                    continue
+                }
+                TextFragment::Stylised(style, s) => {
+                    self.restyle(&mut opened, style);
+                    self.pending.push_back(Token::Text(s));
+                }
+                TextFragment::Group(style, text) => {
+                    self.restyle(&mut opened, Style::Normal);
+                    let styles = STYLES
+                        .into_iter()
+                        .filter(|nesting| style.contains(*nesting));
+                    for nesting in styles.clone() {
+                        self.pending.push_back(Token::StyleOpen(nesting));
+                    }
+                    self.push_text(text);
+                    for nesting in styles.rev() {
+                        self.pending.push_back(Token::StyleClose(nesting));
+                    }
+                }
+                TextFragment::Link(alt, link, title) => {
+                    self.restyle(&mut opened, Style::Normal);
+                    self.pending.push_back(Token::LinkStart(link, title));
+                    self.push_text(alt);
+                    self.pending.push_back(Token::LinkEnd);
+                }
+                TextFragment::Image(alt, src, title, size) => {
+                    self.restyle(&mut opened, Style::Normal);
+                    self.pending.push_back(Token::Image {
+                        alt,
+                        src,
+                        title,
+                        size,
+                    });
+                }
+                TextFragment::FootnoteRef(_, number) => {
+                    self.restyle(&mut opened, Style::Normal);
+                    self.pending.push_back(Token::FootnoteRef(number));
+                }
+                TextFragment::HardBreak => {
+                    self.restyle(&mut opened, Style::Normal);
+                    self.pending.push_back(Token::HardBreak);
+                }
+            }
+        }
+
+        self.restyle(&mut opened, Style::Normal);
+    }
+
+    fn restyle(&mut self, opened: &mut Vec<Style>, styles: Style) {
+        if let Some(idx) = opened.iter().position(|style| !styles.contains(*style)) {
+            for style in opened.drain(idx..).rev() {
+                self.pending.push_back(Token::StyleClose(style));
+            }
+        }
+        for style in STYLES {
+            if styles.contains(style) && !opened.contains(&style) {
+                self.pending.push_back(Token::StyleOpen(style));
+                opened.push(style);
+            }
+        }
+    }
+}
+
+impl<'a> From<Document<'a>> for Tokens<'a> {
+    fn from(document: Document<'a>) -> Self {
+        Self {
+            levels: vec![Level {
+                nodes: document.nodes.into_iter(),
+                lists: Vec::new(),
+                end: None,
+            }],
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(token);
+            }
+
+            let level = self.levels.last_mut()?;
+            match level.nodes.next() {
+                Some(node) => self.push_node(node),
+                None if !level.lists.is_empty() => self.end_lists(0),
+                None => {
+                    if let Some(Level { end: Some(end), .. }) = self.levels.pop() {
+                        return Some(end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{Token::*, *};
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<_> =
+            Tokens::new("# Hi *you*\n- a [l](u)\n```rust\nx\n```\n:::note\nok\n:::").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                HeaderStart(1),
+                Text("Hi ".into()),
+                StyleOpen(Style::Emphasis),
+                Text("you".into()),
+                StyleClose(Style::Emphasis),
+                HeaderEnd(1),
+                ListStart(ListKind::Unordere(0)),
+                ListItemStart(ListKind::Unordere(0)),
+                Text("a ".into()),
+                LinkStart("u".into(), None),
+                Text("l".into()),
+                LinkEnd,
+                ListItemEnd,
+                ListEnd(ListKind::Unordere(0)),
+                FenceOpen("rust".into()),
+                Code("x\n".into()),
+                FenceClose,
+                ContainerStart("note".into()),
+                ParagraphStart,
+                Text("ok".into()),
+                ParagraphEnd,
+                ContainerEnd("note".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        let (a, b) = (ListKind::Unordere(0), ListKind::Unordere(1));
+        let tokens: Vec<_> = Tokens::new("- a\n    - b\n    - c\n- d").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ListStart(a),
+                ListItemStart(a),
+                Text("a".into()),
+                ListStart(b),
+                ListItemStart(b),
+                Text("b".into()),
+                ListItemEnd,
+                ListItemStart(b),
+                Text("c".into()),
+                ListItemEnd,
+                ListEnd(b),
+                ListItemEnd,
+                ListItemStart(a),
+                Text("d".into()),
+                ListItemEnd,
+                ListEnd(a),
+            ]
+        );
+
+        // A list ends where its numbers end differently, and at the end of its container
+        let tokens: Vec<_> = Tokens::new(":::note\n1. a\n1) b\n:::").collect();
+        let (a, ordered) = (ListKind::Oredred(0, '.'), ListKind::Oredred(0, ')'));
+        assert_eq!(
+            tokens,
+            vec![
+                ContainerStart("note".into()),
+                ListStart(a),
+                ListItemStart(a),
+                Text("a".into()),
+                ListItemEnd,
+                ListEnd(a),
+                ListStart(ordered),
+                ListItemStart(ordered),
+                Text("b".into()),
+                ListItemEnd,
+                ListEnd(ordered),
+                ContainerEnd("note".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn balanced() {
+        for s in [
+            "- a\n        - deep\n- b\n\n> q\n\n1. x\n2. y\n    - z",
+            "- [ ] t\n- [x] u\n\n- loose\n\n  more\n* other",
+            ":::a\n- x\n:::b\n- y\n:::\n:::\n- z",
+        ] {
+            let mut open = Vec::new();
+            for token in Tokens::new(s) {
+                match token {
+                    ListStart(kind) => open.push(ListStart(kind)),
+                    ListItemStart(_) | ContainerStart(_) | ParagraphStart => open.push(token),
+                    ListEnd(kind) => assert_eq!(open.pop(), Some(ListStart(kind)), "{s:?}"),
+                    ListItemEnd => assert!(matches!(open.pop(), Some(ListItemStart(_))), "{s:?}"),
+                    ContainerEnd(name) => {
+                        assert_eq!(open.pop(), Some(ContainerStart(name)), "{s:?}")
+                    }
+                    ParagraphEnd => assert_eq!(open.pop(), Some(ParagraphStart), "{s:?}"),
+                    _ => (),
+                }
+            }
+            assert!(open.is_empty(), "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+use crate::{
+    document::{Alignment, CodeBlock, Document, ListKind, Node, Style, Text, TextFragment},
+    md::MarkDown,
+    render::Renderer,
+};
+
+/// The text of a document without any markup, blocks being separated by a blank line.
+#[must_use]
+pub struct PlainText<'a>(pub Document<'a>, pub PlainTextOptions);
+
+impl<'a> PlainText<'a> {
+    pub fn with_options(mut self, options: PlainTextOptions) -> Self {
+        self.1 = options;
+        self
+    }
+}
+
+impl<'a> From<MarkDown<'a>> for PlainText<'a> {
+    fn from(markdown: MarkDown<'a>) -> Self {
+        Self(markdown.0, PlainTextOptions::default())
+    }
+}
+
+impl<'a> fmt::Display for PlainText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.render(&mut PlainTextRenderer {
+            options: &self.1,
+            f,
+            empty: true,
+            in_list: false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlainTextOptions {
+    /// Follow the text of links with their url, as `text (url)`.
+    pub link_urls: bool,
+}
+
+struct PlainTextRenderer<'r, W> {
+    options: &'r PlainTextOptions,
+    f: &'r mut W,
+    empty: bool,
+    in_list: bool,
+}
+
+impl<'r, W: fmt::Write> PlainTextRenderer<'r, W> {
+    fn block_start(&mut self) -> fmt::Result {
+        if !self.empty {
+            self.f.write_str("\n\n")?;
+        }
+        self.empty = false;
+        Ok(())
+    }
+}
+
+impl<'r, W: fmt::Write> Renderer for PlainTextRenderer<'r, W> {
+    fn header(&mut self, _level: usize, text: &Text) -> fmt::Result {
+        self.block_start()?;
+        self.text(text)
+    }
+
+    fn paragraph(&mut self, text: &Text) -> fmt::Result {
+        self.block_start()?;
+        self.text(text)
+    }
+
+    fn quote_start(&mut self, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn quote(&mut self, _deepth: usize, text: &Text) -> fmt::Result {
+        self.block_start()?;
+        self.text(text)
+    }
+
+    fn quote_end(&mut self, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn list_start(&mut self, _kind: &ListKind, _deepth: usize, _items: &[Node]) -> fmt::Result {
+        Ok(())
+    }
+
+    fn list_item(
+        &mut self,
+        _kind: &ListKind,
+        deepth: usize,
+        paragraphs: &[Text],
+        _loose: bool,
+    ) -> fmt::Result {
+        if self.in_list {
+            self.f.write_str("\n")?;
+        } else {
+            self.block_start()?;
+            self.in_list = true;
+        }
+        self.f
+            .write_fmt(format_args!("{:1$}- ", "", 2 * deepth.saturating_sub(1)))?;
+        for (idx, text) in paragraphs.iter().enumerate() {
+            if idx > 0 {
+                self.f.write_str("\n\n")?;
+                self.f
+                    .write_fmt(format_args!("{:1$}", "", 2 * deepth.saturating_sub(1) + 2))?;
+            }
+            self.text(text)?;
+        }
+        Ok(())
+    }
+
+    fn list_item_end(&mut self, _kind: &ListKind, _deepth: usize) -> fmt::Result {
+        Ok(())
+    }
+
+    fn list_end(&mut self, _kind: &ListKind, deepth: usize) -> fmt::Result {
+        if deepth == 0 {
+            self.in_list = false;
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        header: &[Text],
+        _alignments: &[Option<Alignment>],
+        rows: &[Vec<Text>],
+    ) -> fmt::Result {
+        self.block_start()?;
+        for (idx, row) in core::iter::once(header)
+            .chain(rows.iter().map(Vec::as_slice))
+            .enumerate()
+        {
+            if idx > 0 {
+                self.f.write_str("\n")?;
+            }
+            for (idx, cell) in row.iter().enumerate() {
+                if idx > 0 {
+                    self.f.write_str("\t")?;
+                }
+                self.text(cell)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn code_block(&mut self, codeblock: &CodeBlock) -> fmt::Result {
+        self.block_start()?;
+        let code = codeblock.fetch().unwrap_or_default();
+        self.f.write_str(code.strip_suffix('\n').unwrap_or(code))
+    }
+
+    fn container_start(&mut self, _name: &str, _attrs: &[(Cow<str>, Cow<str>)]) -> fmt::Result {
+        Ok(())
+    }
+
+    fn container_end(&mut self, _name: &str) -> fmt::Result {
+        Ok(())
+    }
+
+    fn line_break(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn rule(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn footnotes_start(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn footnote(&mut self, number: usize, text: &Text) -> fmt::Result {
+        self.block_start()?;
+        self.f.write_fmt(format_args!("[{number}] "))?;
+        self.text(text)
+    }
+
+    fn footnotes_end(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn text_fragment(&mut self, fragment: &TextFragment) -> fmt::Result {
+        match fragment {
+            TextFragment::Stylised(style, _) if style.contains(Style::Modifier) => Ok(()),
+            TextFragment::Stylised(_, s) => self.f.write_str(s),
+            TextFragment::Group(_, text) => self.text(text),
+            TextFragment::Link(alt, link, _) => {
+                self.text(alt)?;
+                if self.options.link_urls {
+                    self.f.write_fmt(format_args!(" ({link})"))?;
+                }
+                Ok(())
+            }
+            TextFragment::Image(alt, _, _, _) => self.f.write_str(alt),
+            TextFragment::FootnoteRef(_, number) => self.f.write_fmt(format_args!("[{number}]")),
+            // The line it breaks always ends with a newline already
+            TextFragment::HardBreak => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+
+    use super::*;
+
+    fn plain(s: &str) -> String {
+        PlainText::from(MarkDown::from(s)).to_string()
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(
+            plain("# Title *x*\npara **b** [l](u) ![i](p)\nnext\n\n- a\n    - b\n- c"),
+            "Title x\n\npara b l i\nnext\n\n- a\n  - b\n- c"
+        );
+        assert_eq!(
+            plain("```rs\nlet a = 1;\n```\n> q\n\na | b\n--|--\n1 | 2"),
+            "let a = 1;\n\nq\n\na\tb\n1\t2"
+        );
+        let urls = PlainTextOptions { link_urls: true };
+        assert_eq!(
+            PlainText::from(MarkDown::from("[l](u) ![i](p)"))
+                .with_options(urls)
+                .to_string(),
+            "l (u) i"
+        );
+    }
+}
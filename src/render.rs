@@ -0,0 +1,203 @@
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+use crate::document::{Alignment, CodeBlock, Document, ListKind, Node, Style, Text, TextFragment};
+
+/// An output format, called by `Document::render` for each node in the order they come.
+pub trait Renderer {
+    /// Called on every node before it gets rendered, containers' children included.
+    fn node(&mut self, _node: &Node) {}
+
+    fn header(&mut self, level: usize, text: &Text) -> fmt::Result;
+    fn paragraph(&mut self, text: &Text) -> fmt::Result;
+
+    fn quote_start(&mut self, deepth: usize) -> fmt::Result;
+    fn quote(&mut self, deepth: usize, text: &Text) -> fmt::Result;
+    fn quote_end(&mut self, deepth: usize) -> fmt::Result;
+
+    /// `items` run from the item opening the list to the last item of the outermost list.
+    fn list_start(&mut self, kind: &ListKind, deepth: usize, items: &[Node]) -> fmt::Result;
+    /// `loose` items have their paragraphs set apart, as in a list whose items are parted by
+    /// blank lines. The lists nested in an item come before its end.
+    fn list_item(
+        &mut self,
+        kind: &ListKind,
+        deepth: usize,
+        paragraphs: &[Text],
+        loose: bool,
+    ) -> fmt::Result;
+    fn list_item_end(&mut self, kind: &ListKind, deepth: usize) -> fmt::Result;
+    fn list_end(&mut self, kind: &ListKind, deepth: usize) -> fmt::Result;
+
+    fn table(
+        &mut self,
+        header: &[Text],
+        alignments: &[Option<Alignment>],
+        rows: &[Vec<Text>],
+    ) -> fmt::Result;
+    fn code_block(&mut self, codeblock: &CodeBlock) -> fmt::Result;
+
+    fn container_start(&mut self, name: &str, attrs: &[(Cow<str>, Cow<str>)]) -> fmt::Result;
+    fn container_end(&mut self, name: &str) -> fmt::Result;
+
+    fn line_break(&mut self) -> fmt::Result;
+    fn rule(&mut self) -> fmt::Result;
+
+    /// The footnotes end the document, all of them between one start and one end.
+    fn footnotes_start(&mut self) -> fmt::Result;
+    fn footnote(&mut self, number: usize, text: &Text) -> fmt::Result;
+    fn footnotes_end(&mut self) -> fmt::Result;
+
+    fn text_fragment(&mut self, fragment: &TextFragment) -> fmt::Result;
+
+    /// Renders the fragments one by one, those of a group taking on its style, a renderer styling
+    /// across fragments overrides it.
+    fn text(&mut self, text: &Text) -> fmt::Result {
+        text_fragments(self, &text.content, Style::empty())
+    }
+}
+
+fn text_fragments<R: Renderer + ?Sized>(
+    renderer: &mut R,
+    content: &[TextFragment],
+    around: Style,
+) -> fmt::Result {
+    for fragment in content {
+        match fragment {
+            TextFragment::Group(style, text) => {
+                text_fragments(renderer, &text.content, around | *style)?
+            }
+            TextFragment::Stylised(style, s) if !style.contains(Style::Modifier) => {
+                renderer.text_fragment(&TextFragment::Stylised(*style | around, s.clone()))?
+            }
+            fragment => renderer.text_fragment(fragment)?,
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Document<'a> {
+    pub fn render<R: Renderer>(&self, renderer: &mut R) -> fmt::Result {
+        render_nodes(&self.nodes, renderer)
+    }
+}
+
+fn render_nodes(nodes: &[Node], renderer: &mut impl Renderer) -> fmt::Result {
+    let mut lists: Vec<ListKind> = Vec::new();
+    let mut quote_deepth = 0;
+    let mut in_footnotes = false;
+
+    for (idx, node) in nodes.iter().enumerate() {
+        renderer.node(node);
+
+        if !matches!(node, Node::List(_, _, _)) {
+            end_lists(&mut lists, 0, renderer)?;
+        }
+        if in_footnotes && !matches!(node, Node::Footnote(_, _)) {
+            renderer.footnotes_end()?;
+            in_footnotes = false;
+        }
+        let deepth = match node {
+            Node::Quote(deepth, _) => *deepth,
+            _ => 0,
+        };
+        while quote_deepth > deepth {
+            renderer.quote_end(quote_deepth)?;
+            quote_deepth -= 1;
+        }
+        while quote_deepth < deepth {
+            quote_deepth += 1;
+            renderer.quote_start(quote_deepth)?;
+        }
+
+        match node {
+            Node::Header(level, text) => renderer.header(*level, text)?,
+            Node::Paragraphe(text) => renderer.paragraph(text)?,
+            Node::Quote(deepth, text) => renderer.quote(*deepth, text)?,
+            Node::Table {
+                header,
+                alignments,
+                rows,
+            } => renderer.table(header, alignments, rows)?,
+            Node::CodeBlock(codeblock) => renderer.code_block(codeblock)?,
+            Node::List(list_kind, paragraphs, loose) => {
+                let deepth = list_kind.deepth();
+                let kept = lists
+                    .iter()
+                    .take_while(|pre_list| pre_list.deepth() < deepth)
+                    .count();
+                let kept = kept
+                    + usize::from(
+                        lists
+                            .get(kept)
+                            .is_some_and(|pre_list| list_kind.continues(pre_list)),
+                    );
+                end_lists(&mut lists, kept, renderer)?;
+
+                match lists.last() {
+                    Some(pre_list) if pre_list.deepth() == deepth => {
+                        renderer.list_item_end(pre_list, lists.len())?
+                    }
+                    // A list nests in the item above it, an item deeper by several levels in
+                    // empty items of lists of its own kind
+                    pre_list => {
+                        let items_end = nodes[idx..]
+                            .iter()
+                            .position(|node| !matches!(node, Node::List(_, _, _)))
+                            .map_or(nodes.len(), |end| idx + end);
+                        let first_deepth =
+                            pre_list.map_or(deepth, |pre_list| pre_list.deepth() + 1);
+                        for list_deepth in first_deepth..deepth {
+                            let list = list_kind.with_deepth(list_deepth);
+                            renderer.list_start(&list, lists.len(), &nodes[idx..items_end])?;
+                            lists.push(list);
+                            renderer.list_item(&list, lists.len(), &[], false)?;
+                        }
+                        renderer.list_start(list_kind, lists.len(), &nodes[idx..items_end])?;
+                        lists.push(*list_kind);
+                    }
+                }
+                renderer.list_item(list_kind, lists.len(), paragraphs, *loose)?;
+            }
+            Node::Container {
+                name,
+                attrs,
+                children,
+            } => {
+                renderer.container_start(name, attrs)?;
+                render_nodes(children, renderer)?;
+                renderer.container_end(name)?;
+            }
+            Node::LineBreak => renderer.line_break()?,
+            Node::Rule => renderer.rule()?,
+            Node::Footnote(number, text) => {
+                if !in_footnotes {
+                    renderer.footnotes_start()?;
+                    in_footnotes = true;
+                }
+                renderer.footnote(*number, text)?;
+            }
+        }
+    }
+
+    end_lists(&mut lists, 0, renderer)?;
+    while quote_deepth > 0 {
+        renderer.quote_end(quote_deepth)?;
+        quote_deepth -= 1;
+    }
+    if in_footnotes {
+        renderer.footnotes_end()?;
+    }
+
+    Ok(())
+}
+
+// Ends the lists nested past the `kept` first ones, along the items they are in
+fn end_lists(lists: &mut Vec<ListKind>, kept: usize, renderer: &mut impl Renderer) -> fmt::Result {
+    while lists.len() > kept {
+        let pre_list = lists.pop().unwrap();
+        renderer.list_item_end(&pre_list, lists.len() + 1)?;
+        renderer.list_end(&pre_list, lists.len())?;
+    }
+    Ok(())
+}
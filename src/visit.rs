@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::document::{CodeBlock, Document, ListKind, Node, Text, TextFragment};
+
+/// Called by `Document::walk` on the nodes in the order they come, every method doing nothing
+/// unless overridden.
+///
+/// ```
+/// use htmd::{CodeBlock, MarkDown, Visitor};
+///
+/// struct CodeBlocks(usize);
+///
+/// impl Visitor for CodeBlocks {
+///     fn visit_code_block(&mut self, _codeblock: &CodeBlock) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// let mut code_blocks = CodeBlocks(0);
+/// MarkDown::from("```\na\n```\n:::note\n```\nb\n```\n:::").0.walk(&mut code_blocks);
+/// assert_eq!(code_blocks.0, 2);
+/// ```
+pub trait Visitor {
+    fn visit_header(&mut self, _level: usize, _text: &Text) {}
+    fn visit_paragraph(&mut self, _text: &Text) {}
+    fn visit_list_item(&mut self, _kind: &ListKind, _paragraphs: &[Text]) {}
+    fn visit_quote(&mut self, _deepth: usize, _text: &Text) {}
+    fn visit_code_block(&mut self, _codeblock: &CodeBlock) {}
+    fn visit_table(&mut self, _header: &[Text], _rows: &[Vec<Text>]) {}
+    fn visit_footnote(&mut self, _number: usize, _text: &Text) {}
+
+    /// Called on the fragments of every text, after the node holding it and with links
+    /// followed by the fragments of their alt.
+    fn visit_text_fragment(&mut self, _fragment: &TextFragment) {}
+}
+
+impl<'a> Document<'a> {
+    pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+        walk_nodes(&self.nodes, visitor)
+    }
+
+    /// The alt and url of every link, reference links included, in the order they come.
+    pub fn links(&self) -> Vec<(&Text<'a>, &str)> {
+        self.fragments()
+            .into_iter()
+            .filter_map(|fragment| match fragment {
+                TextFragment::Link(alt, link, _) => Some((alt, link.as_ref())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The alt and path of every image, in the order they come.
+    pub fn images(&self) -> Vec<(&str, &str)> {
+        self.fragments()
+            .into_iter()
+            .filter_map(|fragment| match fragment {
+                TextFragment::Image(alt, path, _, _) => Some((alt.as_ref(), path.as_ref())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The words of the text as rendered to plain text, so `**bold**` is one word and code
+    /// spans count. Code blocks count only if `code_blocks` is set.
+    ///
+    /// ```
+    /// let md = htmd::MarkDown::from("# Hello *big* world\n\n**Bold**ly run `cargo doc`\n\n```\nfn main() {}\n```");
+    /// assert_eq!(md.0.word_count(false), 7);
+    /// assert_eq!(md.0.word_count(true), 10);
+    /// ```
+    pub fn word_count(&self, code_blocks: bool) -> usize {
+        let mut counter = WordCounter {
+            code_blocks,
+            words: 0,
+        };
+        self.walk(&mut counter);
+        counter.words
+    }
+
+    /// Time taken to read the words of the document, code blocks left out, at `words_per_minute`.
+    pub fn reading_time(&self, words_per_minute: usize) -> Duration {
+        let words = self.word_count(false) as u64;
+        Duration::from_secs((words * 60).div_ceil(words_per_minute.max(1) as u64))
+    }
+
+    // A visitor can't keep what it is given, so the fragments are gathered here to be borrowed
+    fn fragments(&self) -> Vec<&TextFragment<'a>> {
+        let mut fragments = Vec::new();
+        collect_fragments(&self.nodes, &mut fragments);
+        fragments
+    }
+}
+
+fn collect_fragments<'d, 'a>(nodes: &'d [Node<'a>], fragments: &mut Vec<&'d TextFragment<'a>>) {
+    for node in nodes {
+        match node {
+            Node::Header(_, text)
+            | Node::Paragraphe(text)
+            | Node::Quote(_, text)
+            | Node::Footnote(_, text) => collect_text_fragments(text, fragments),
+            Node::List(_, paragraphs, _) => {
+                for text in paragraphs {
+                    collect_text_fragments(text, fragments);
+                }
+            }
+            Node::Table { header, rows, .. } => {
+                for cell in header.iter().chain(rows.iter().flatten()) {
+                    collect_text_fragments(cell, fragments);
+                }
+            }
+            Node::Container { children, .. } => collect_fragments(children, fragments),
+            Node::CodeBlock(_) | Node::LineBreak | Node::Rule => (),
+        }
+    }
+}
+
+fn collect_text_fragments<'d, 'a>(text: &'d Text<'a>, fragments: &mut Vec<&'d TextFragment<'a>>) {
+    for fragment in text.content.iter() {
+        fragments.push(fragment);
+        if let TextFragment::Group(_, text) | TextFragment::Link(text, _, _) = fragment {
+            collect_text_fragments(text, fragments);
+        }
+    }
+}
+
+struct WordCounter {
+    code_blocks: bool,
+    words: usize,
+}
+
+impl WordCounter {
+    fn count_text(&mut self, text: &Text) {
+        self.words += text.to_plain_text().split_whitespace().count();
+    }
+}
+
+impl Visitor for WordCounter {
+    fn visit_header(&mut self, _level: usize, text: &Text) {
+        self.count_text(text);
+    }
+
+    fn visit_paragraph(&mut self, text: &Text) {
+        self.count_text(text);
+    }
+
+    fn visit_list_item(&mut self, _kind: &ListKind, paragraphs: &[Text]) {
+        paragraphs.iter().for_each(|text| self.count_text(text));
+    }
+
+    fn visit_quote(&mut self, _deepth: usize, text: &Text) {
+        self.count_text(text);
+    }
+
+    fn visit_code_block(&mut self, codeblock: &CodeBlock) {
+        if self.code_blocks {
+            self.words += codeblock
+                .fetch()
+                .unwrap_or_default()
+                .split_whitespace()
+                .count();
+        }
+    }
+
+    fn visit_table(&mut self, header: &[Text], rows: &[Vec<Text>]) {
+        for cell in header.iter().chain(rows.iter().flatten()) {
+            self.count_text(cell);
+        }
+    }
+
+    fn visit_footnote(&mut self, _number: usize, text: &Text) {
+        self.count_text(text);
+    }
+}
+
+fn walk_nodes(nodes: &[Node], visitor: &mut impl Visitor) {
+    for node in nodes {
+        match node {
+            Node::Header(level, text) => {
+                visitor.visit_header(*level, text);
+                walk_text(text, visitor);
+            }
+            Node::Paragraphe(text) => {
+                visitor.visit_paragraph(text);
+                walk_text(text, visitor);
+            }
+            Node::List(kind, paragraphs, _) => {
+                visitor.visit_list_item(kind, paragraphs);
+                for text in paragraphs {
+                    walk_text(text, visitor);
+                }
+            }
+            Node::Quote(deepth, text) => {
+                visitor.visit_quote(*deepth, text);
+                walk_text(text, visitor);
+            }
+            Node::Table { header, rows, .. } => {
+                visitor.visit_table(header, rows);
+                for cell in header.iter().chain(rows.iter().flatten()) {
+                    walk_text(cell, visitor);
+                }
+            }
+            Node::CodeBlock(codeblock) => visitor.visit_code_block(codeblock),
+            Node::Container { children, .. } => walk_nodes(children, visitor),
+            Node::Footnote(number, text) => {
+                visitor.visit_footnote(*number, text);
+                walk_text(text, visitor);
+            }
+            Node::LineBreak | Node::Rule => (),
+        }
+    }
+}
+
+fn walk_text(text: &Text, visitor: &mut impl Visitor) {
+    for fragment in text.content.iter() {
+        visitor.visit_text_fragment(fragment);
+        if let TextFragment::Group(_, text) | TextFragment::Link(text, _, _) = fragment {
+            walk_text(text, visitor);
+        }
+    }
+}